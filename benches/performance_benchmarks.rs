@@ -1,9 +1,12 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use shipyard::World;
-use tag::{initialize_world, TICK};
+use tag::{initialize_world, SimConfig, TICK};
 
 fn criterion_benchmark(c: &mut Criterion) {
-    let world = initialize_world(1000);
+    let world = initialize_world(SimConfig {
+        player_count: 1000,
+        ..SimConfig::default()
+    });
     c.bench_function("tick world", |b| {
         b.iter(|| world.run_workload(TICK).unwrap())
     });
@@ -0,0 +1,125 @@
+use crate::entities_components::{Geo2D, TagState, Velocity};
+
+/// How a player is kept within the arena when moving past one of its edges
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BoundaryPolicy {
+    /// Leave through one edge, enter through the opposite one
+    Wrap,
+    /// Reflect off the edge, reversing the velocity towards it
+    Bounce,
+    /// Stop at the edge
+    Clamp,
+}
+
+impl BoundaryPolicy {
+    /// Move `pos` back within `[0, width) x [0, height)` after it has been moved.
+    /// Bouncing also reflects `vel`, such that the player keeps heading into the arena.
+    pub fn apply(&self, pos: &mut Geo2D, vel: &mut Velocity, width: f32, height: f32) {
+        let bounds = [width, height];
+        for axis in 0..2 {
+            let limit = bounds[axis];
+            match self {
+                // Rounding can bring positions just short of zero onto the far edge
+                BoundaryPolicy::Wrap => {
+                    pos[axis] = pos[axis].rem_euclid(limit).min(last_within(limit))
+                }
+                BoundaryPolicy::Clamp => pos[axis] = pos[axis].clamp(0.0, last_within(limit)),
+                BoundaryPolicy::Bounce => {
+                    if pos[axis] < 0.0 {
+                        pos[axis] = -pos[axis];
+                        vel.0[axis] = vel.0[axis].abs();
+                    } else if pos[axis] > limit {
+                        pos[axis] = 2.0 * limit - pos[axis];
+                        vel.0[axis] = -vel.0[axis].abs();
+                    }
+                    // Very fast players could still be outside after one reflection
+                    pos[axis] = pos[axis].clamp(0.0, last_within(limit));
+                }
+            }
+        }
+    }
+}
+
+/// The largest position short of the far edge at `limit`
+fn last_within(limit: f32) -> f32 {
+    if limit > 0.0 {
+        f32::from_bits(limit.to_bits() - 1)
+    } else {
+        0.0
+    }
+}
+
+/// The [BoundaryPolicy] which applies to players, depending on their [TagState].
+#[derive(Clone, Debug)]
+pub struct BoundaryRules {
+    pub not_it: BoundaryPolicy,
+    pub it: BoundaryPolicy,
+}
+
+impl BoundaryRules {
+    /// Every player follows the same policy
+    pub fn uniform(policy: BoundaryPolicy) -> Self {
+        Self {
+            not_it: policy,
+            it: policy,
+        }
+    }
+
+    /// Untagged players can wrap the edges, tagged players are held back by `it_policy`.
+    pub fn wrap_only_not_it(it_policy: BoundaryPolicy) -> Self {
+        Self {
+            not_it: BoundaryPolicy::Wrap,
+            it: it_policy,
+        }
+    }
+
+    pub fn for_state(&self, state: &TagState) -> BoundaryPolicy {
+        match state {
+            TagState::NotIt
+            | TagState::Frozen
+            | TagState::Recovered
+            | TagState::Eliminated
+            | TagState::Jailed => self.not_it,
+            TagState::It => self.it,
+        }
+    }
+}
+
+/// Untagged players wrap the edges to get to safety, tagged players bounce off them.
+impl Default for BoundaryRules {
+    fn default() -> Self {
+        Self::wrap_only_not_it(BoundaryPolicy::Bounce)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BoundaryPolicy;
+    use crate::entities_components::Velocity;
+
+    #[test]
+    fn test_boundary_policies() {
+        let apply = |policy: BoundaryPolicy, pos: [f32; 2]| {
+            let mut pos = pos;
+            let mut vel = Velocity::new(1.0, -1.0);
+            policy.apply(&mut pos, &mut vel, 1024.0, 1024.0);
+            (pos, vel)
+        };
+
+        let (pos, vel) = apply(BoundaryPolicy::Wrap, [1030.0, -4.0]);
+        assert_eq!(pos, [6.0, 1020.0]);
+        assert_eq!(vel, Velocity::new(1.0, -1.0));
+        // Just short of zero wraps onto the far edge, but stays within the arena
+        let (pos, _) = apply(BoundaryPolicy::Wrap, [-1e-6, 0.0]);
+        assert!(pos[0] < 1024.0 && pos[0] > 1023.9);
+
+        let (pos, vel) = apply(BoundaryPolicy::Bounce, [1030.0, -4.0]);
+        assert_eq!(pos, [1018.0, 4.0]);
+        assert_eq!(vel, Velocity::new(-1.0, 1.0));
+
+        let (pos, vel) = apply(BoundaryPolicy::Clamp, [1030.0, -4.0]);
+        assert!(pos[0] < 1024.0 && pos[0] > 1023.9);
+        assert_eq!(pos[1], 0.0);
+        assert_eq!(vel, Velocity::new(1.0, -1.0));
+    }
+}
@@ -0,0 +1,366 @@
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use super::{MapLayout, Tile};
+
+/// Procedural arenas, to try behaviours on many different layouts.
+/// The same generator, size and seed always give the same arena, and a player can get
+/// from every open part of a generated arena to every other one.
+#[derive(Clone, Debug)]
+pub enum ArenaGenerator {
+    /// Corridors `corridor_width` cells wide between walls one cell thick.
+    /// `braid` is the chance of removing each remaining wall between two corridors,
+    /// adding loops to the maze so that players are not easily cornered.
+    Maze { corridor_width: usize, braid: f64 },
+    /// Cellular automaton: cells start as walls with a chance of `fill`,
+    /// and are smoothed `smoothing_steps` times with their neighbours.
+    Cave { fill: f64, smoothing_steps: usize },
+    /// `count` square pillars of `size` cells scattered over an open arena
+    Pillars { count: usize, size: usize },
+}
+
+impl ArenaGenerator {
+    /// Parse the name given on the command line, with default parameters
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "maze" => Some(ArenaGenerator::Maze {
+                corridor_width: 3,
+                braid: 0.1,
+            }),
+            "cave" => Some(ArenaGenerator::Cave {
+                fill: 0.45,
+                smoothing_steps: 4,
+            }),
+            "pillars" => Some(ArenaGenerator::Pillars { count: 24, size: 3 }),
+            _ => None,
+        }
+    }
+
+    /// An arena of `cols` by `rows` cells, every open part of which a player of radius
+    /// `player_size` can reach
+    pub fn generate(
+        &self,
+        cols: usize,
+        rows: usize,
+        cell_size: f32,
+        player_size: f32,
+        seed: u64,
+    ) -> MapLayout {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut tiles = match self {
+            ArenaGenerator::Maze {
+                corridor_width,
+                braid,
+            } => maze(cols, rows, (*corridor_width).max(1), *braid, &mut rng),
+            ArenaGenerator::Cave {
+                fill,
+                smoothing_steps,
+            } => cave(cols, rows, *fill, *smoothing_steps, &mut rng),
+            ArenaGenerator::Pillars { count, size } => {
+                pillars(cols, rows, *count, (*size).max(1), &mut rng)
+            }
+        };
+        seal_unreachable(cols, rows, cell_size, player_size, &mut tiles);
+
+        MapLayout {
+            cols,
+            rows,
+            cell_size,
+            tiles,
+        }
+    }
+}
+
+fn maze(cols: usize, rows: usize, width: usize, braid: f64, rng: &mut ChaCha8Rng) -> Vec<Tile> {
+    let mut tiles = vec![Tile::Wall; cols * rows];
+    // Rooms of the maze, `width` cells wide with a wall on their top and left
+    let room_cols = (cols.saturating_sub(1) / (width + 1)).max(1);
+    let room_rows = (rows.saturating_sub(1) / (width + 1)).max(1);
+    let carve = |col: usize, row: usize, tiles: &mut [Tile]| {
+        if col < cols && row < rows {
+            tiles[row * cols + col] = Tile::Floor;
+        }
+    };
+    let origin = |room: usize| 1 + room * (width + 1);
+
+    for room_row in 0..room_rows {
+        for room_col in 0..room_cols {
+            for dy in 0..width {
+                for dx in 0..width {
+                    carve(origin(room_col) + dx, origin(room_row) + dy, &mut tiles);
+                }
+            }
+        }
+    }
+
+    // Opening the wall between two neighbouring rooms
+    let open_between = |a: (usize, usize), b: (usize, usize), tiles: &mut [Tile]| {
+        for offset in 0..width {
+            if a.1 == b.1 {
+                let col = origin(a.0.max(b.0)) - 1;
+                carve(col, origin(a.1) + offset, tiles);
+            } else {
+                let row = origin(a.1.max(b.1)) - 1;
+                carve(origin(a.0) + offset, row, tiles);
+            }
+        }
+    };
+    let neighbours = |(col, row): (usize, usize)| {
+        let mut neighbours = Vec::with_capacity(4);
+        if col > 0 {
+            neighbours.push((col - 1, row));
+        }
+        if row > 0 {
+            neighbours.push((col, row - 1));
+        }
+        if col + 1 < room_cols {
+            neighbours.push((col + 1, row));
+        }
+        if row + 1 < room_rows {
+            neighbours.push((col, row + 1));
+        }
+        neighbours
+    };
+
+    // Depth-first backtracking carves a spanning tree over the rooms
+    let mut visited = vec![false; room_cols * room_rows];
+    let mut stack = vec![(0, 0)];
+    visited[0] = true;
+    while let Some(&room) = stack.last() {
+        let unvisited: Vec<(usize, usize)> = neighbours(room)
+            .into_iter()
+            .filter(|(col, row)| !visited[row * room_cols + col])
+            .collect();
+        match unvisited.choose(rng) {
+            Some(&next) => {
+                visited[next.1 * room_cols + next.0] = true;
+                open_between(room, next, &mut tiles);
+                stack.push(next);
+            }
+            None => {
+                stack.pop();
+            }
+        }
+    }
+
+    // Braiding only removes walls, the maze stays connected
+    for room_row in 0..room_rows {
+        for room_col in 0..room_cols {
+            for next in neighbours((room_col, room_row)) {
+                if next > (room_col, room_row) && rng.gen_bool(braid) {
+                    open_between((room_col, room_row), next, &mut tiles);
+                }
+            }
+        }
+    }
+
+    tiles
+}
+
+fn cave(cols: usize, rows: usize, fill: f64, steps: usize, rng: &mut ChaCha8Rng) -> Vec<Tile> {
+    let border =
+        |col: usize, row: usize| col == 0 || row == 0 || col + 1 == cols || row + 1 == rows;
+    let mut walls: Vec<bool> = (0..cols * rows)
+        .map(|cell| border(cell % cols, cell / cols) || rng.gen_bool(fill))
+        .collect();
+
+    for _ in 0..steps {
+        walls = (0..cols * rows)
+            .map(|cell| {
+                let (col, row) = (cell % cols, cell / cols);
+                if border(col, row) {
+                    return true;
+                }
+                let neighbour_walls = (row - 1..=row + 1)
+                    .flat_map(|r| (col - 1..=col + 1).map(move |c| (c, r)))
+                    .filter(|&(c, r)| (c, r) != (col, row) && walls[r * cols + c])
+                    .count();
+                match neighbour_walls {
+                    0..=3 => false,
+                    4 => walls[cell],
+                    _ => true,
+                }
+            })
+            .collect();
+    }
+
+    walls
+        .into_iter()
+        .map(|wall| if wall { Tile::Wall } else { Tile::Floor })
+        .collect()
+}
+
+fn pillars(cols: usize, rows: usize, count: usize, size: usize, rng: &mut ChaCha8Rng) -> Vec<Tile> {
+    let mut tiles = vec![Tile::Floor; cols * rows];
+    if cols <= size || rows <= size {
+        return tiles;
+    }
+    for _ in 0..count {
+        let col = rng.gen_range(0..cols - size);
+        let row = rng.gen_range(0..rows - size);
+        for r in row..row + size {
+            for c in col..col + size {
+                tiles[r * cols + c] = Tile::Wall;
+            }
+        }
+    }
+    tiles
+}
+
+/// Distance from the middle of cell `a` to the nearest point of cell `b`, both as `(col, row)`
+fn gap(a: (usize, usize), b: (usize, usize), cell_size: f32) -> f32 {
+    let axis = |a: usize, b: usize| {
+        let cells = (a as f32 - b as f32).abs();
+        (cells * cell_size - cell_size / 2.0).max(0.0)
+    };
+    let (dx, dy) = (axis(a.0, b.0), axis(a.1, b.1));
+    f32::sqrt(dx * dx + dy * dy)
+}
+
+/// Cells around `cell` whose nearest point is closer than `radius` to its middle
+fn cells_within(
+    cols: usize,
+    rows: usize,
+    cell: usize,
+    cell_size: f32,
+    radius: f32,
+) -> impl Iterator<Item = usize> {
+    let (col, row) = (cell % cols, cell / cols);
+    let reach = (radius / cell_size).ceil() as usize + 1;
+    (row.saturating_sub(reach)..(row + reach + 1).min(rows))
+        .flat_map(move |r| {
+            (col.saturating_sub(reach)..(col + reach + 1).min(cols)).map(move |c| (c, r))
+        })
+        .filter(move |other| gap((col, row), *other, cell_size) < radius)
+        .map(move |(c, r)| r * cols + c)
+}
+
+/// Open cells whose middle is at least `player_size` away from every wall, so a player fits there.
+/// Like [crate::NavGrid], the arena edges are left to the boundary policy.
+fn passable(
+    cols: usize,
+    rows: usize,
+    cell_size: f32,
+    player_size: f32,
+    tiles: &[Tile],
+) -> Vec<bool> {
+    (0..tiles.len())
+        .map(|cell| {
+            tiles[cell] != Tile::Wall
+                && cells_within(cols, rows, cell, cell_size, player_size)
+                    .all(|other| tiles[other] != Tile::Wall)
+        })
+        .collect()
+}
+
+/// Region of every `open` cell, connected through cell edges, and the size of every region
+fn regions(cols: usize, rows: usize, open: &[bool]) -> (Vec<usize>, Vec<usize>) {
+    let mut region = vec![usize::MAX; open.len()];
+    let mut sizes = Vec::new();
+    for start in 0..open.len() {
+        if !open[start] || region[start] != usize::MAX {
+            continue;
+        }
+        let id = sizes.len();
+        let mut size = 0;
+        let mut stack = vec![start];
+        region[start] = id;
+        while let Some(cell) = stack.pop() {
+            size += 1;
+            let (col, row) = (cell % cols, cell / cols);
+            let mut visit = |c: usize, r: usize| {
+                let next = r * cols + c;
+                if open[next] && region[next] == usize::MAX {
+                    region[next] = id;
+                    stack.push(next);
+                }
+            };
+            if col > 0 {
+                visit(col - 1, row);
+            }
+            if row > 0 {
+                visit(col, row - 1);
+            }
+            if col + 1 < cols {
+                visit(col + 1, row);
+            }
+            if row + 1 < rows {
+                visit(col, row + 1);
+            }
+        }
+        sizes.push(size);
+    }
+    (region, sizes)
+}
+
+/// Keep the largest region a player of radius `player_size` can move around in, and wall up
+/// every open cell out of its reach. Passages narrower than a player do not connect regions,
+/// so players are never spawned where they cannot get out.
+fn seal_unreachable(
+    cols: usize,
+    rows: usize,
+    cell_size: f32,
+    player_size: f32,
+    tiles: &mut [Tile],
+) {
+    let passable = passable(cols, rows, cell_size, player_size, tiles);
+    let (region, sizes) = regions(cols, rows, &passable);
+    let largest = match (0..sizes.len()).max_by_key(|id| sizes[*id]) {
+        Some(largest) => largest,
+        // Nowhere fits a player, the arena is left as it is
+        None => return,
+    };
+
+    // Open cells overlapped by a player moving around the region stay open
+    let mut reachable = vec![false; tiles.len()];
+    for cell in (0..tiles.len()).filter(|cell| region[*cell] == largest) {
+        for other in cells_within(cols, rows, cell, cell_size, player_size) {
+            reachable[other] = true;
+        }
+        reachable[cell] = true;
+    }
+    for (tile, reachable) in tiles.iter_mut().zip(reachable) {
+        if !reachable {
+            *tile = Tile::Wall;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{gap, passable, regions, ArenaGenerator};
+    use crate::arena::Tile;
+
+    #[test]
+    fn test_generated_arenas_are_connected() {
+        let (cell_size, player_size) = (16.0, 10.0);
+        for name in ["maze", "cave", "pillars"].iter() {
+            let map = ArenaGenerator::from_name(name).unwrap().generate(
+                40,
+                30,
+                cell_size,
+                player_size,
+                7,
+            );
+
+            // The cells a player fits in are a single region
+            let fits = passable(map.cols, map.rows, cell_size, player_size, &map.tiles);
+            let (region, sizes) = regions(map.cols, map.rows, &fits);
+            assert_eq!(sizes.len(), 1, "{} has {} regions", name, sizes.len());
+
+            // And every open cell can be touched by a player in it
+            for cell in (0..map.tiles.len()).filter(|cell| map.tiles[*cell] != Tile::Wall) {
+                let at = |cell: usize| (cell % map.cols, cell / map.cols);
+                assert!(
+                    (0..map.tiles.len())
+                        .filter(|other| region[*other] == 0)
+                        .any(|other| other == cell
+                            || gap(at(other), at(cell), cell_size) < player_size),
+                    "{} has an open cell out of reach",
+                    name
+                );
+            }
+        }
+    }
+}
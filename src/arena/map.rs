@@ -0,0 +1,258 @@
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use super::{Shape, Zone};
+
+/// What a single cell of a map holds
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tile {
+    Floor,
+    /// Blocks movement
+    Wall,
+    /// Players are placed in spawn cells, anywhere if a map has none
+    Spawn,
+    SafeZone,
+    /// Any other kind of ground, keyed by its colour as `0xRRGGBB` in images,
+    /// or by its character in text maps
+    Terrain(u32),
+}
+
+impl Tile {
+    /// White is floor, black is wall, green is safe zone and red is spawn.
+    pub fn from_rgb([r, g, b]: [u8; 3]) -> Self {
+        match [r, g, b] {
+            [255, 255, 255] => Tile::Floor,
+            [0, 0, 0] => Tile::Wall,
+            [0, 255, 0] => Tile::SafeZone,
+            [255, 0, 0] => Tile::Spawn,
+            _ => Tile::Terrain(u32::from_be_bytes([0, r, g, b])),
+        }
+    }
+
+    /// `.` is floor, `#` is wall, `G` is safe zone and `S` is spawn.
+    pub fn from_char(c: char) -> Self {
+        match c {
+            '.' | ' ' => Tile::Floor,
+            '#' => Tile::Wall,
+            'G' => Tile::SafeZone,
+            'S' => Tile::Spawn,
+            other => Tile::Terrain(other as u32),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(std::io::Error),
+    Png(png::DecodingError),
+    /// The map has no cells, or its cells do not fill the grid
+    Malformed(String),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io(err) => write!(f, "could not read map: {}", err),
+            MapError::Png(err) => write!(f, "could not decode map image: {}", err),
+            MapError::Malformed(reason) => write!(f, "malformed map: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl From<std::io::Error> for MapError {
+    fn from(err: std::io::Error) -> Self {
+        MapError::Io(err)
+    }
+}
+
+impl From<png::DecodingError> for MapError {
+    fn from(err: png::DecodingError) -> Self {
+        MapError::Png(err)
+    }
+}
+
+/// An arena designed outside the code, as a grid of [Tile]s.
+/// Every cell is `cell_size` wide, which gives the size of the arena.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MapLayout {
+    pub cols: usize,
+    pub rows: usize,
+    pub cell_size: f32,
+    /// Row-major
+    pub tiles: Vec<Tile>,
+}
+
+impl MapLayout {
+    /// Load a `.png` image, or a text map otherwise, depending on the extension of `path`.
+    /// RON maps are not supported, they are refused rather than read as text maps.
+    pub fn load(path: impl AsRef<Path>, cell_size: f32) -> Result<Self, MapError> {
+        let path = path.as_ref();
+        let has_extension = |wanted: &str| {
+            path.extension()
+                .map_or(false, |ext| ext.eq_ignore_ascii_case(wanted))
+        };
+        if has_extension("ron") {
+            Err(MapError::Malformed(
+                "RON maps are not supported, use a .png image or a text map".to_string(),
+            ))
+        } else if has_extension("png") {
+            Self::from_png(File::open(path)?, cell_size)
+        } else {
+            let mut text = String::new();
+            File::open(path)?.read_to_string(&mut text)?;
+            Self::from_text(&text, cell_size)
+        }
+    }
+
+    /// One character per cell, one line per row. Trailing empty lines are ignored.
+    pub fn from_text(text: &str, cell_size: f32) -> Result<Self, MapError> {
+        let lines: Vec<&str> = text
+            .lines()
+            .map(|line| line.trim_end_matches('\r'))
+            .collect();
+        let rows = lines
+            .iter()
+            .rposition(|line| !line.is_empty())
+            .map_or(0, |last| last + 1);
+        let cols = lines[..rows]
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        // Short rows are padded with floor
+        let mut tiles = Vec::with_capacity(cols * rows);
+        for line in lines[..rows].iter() {
+            let row: Vec<Tile> = line.chars().map(Tile::from_char).collect();
+            tiles.extend(row.iter().cloned());
+            tiles.extend(std::iter::repeat(Tile::Floor).take(cols - row.len()));
+        }
+
+        Self::new(cols, rows, cell_size, tiles)
+    }
+
+    /// One pixel per cell, see [Tile::from_rgb]. Transparent pixels are floor.
+    pub fn from_png<R: Read>(reader: R, cell_size: f32) -> Result<Self, MapError> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let (info, mut image) = decoder.read_info()?;
+        let mut buffer = vec![0; info.buffer_size()];
+        image.next_frame(&mut buffer)?;
+
+        let channels = info.color_type.samples();
+        let tiles = buffer
+            .chunks_exact(channels)
+            .take(info.width as usize * info.height as usize)
+            .map(|pixel| {
+                let (rgb, alpha) = match pixel.len() {
+                    1 => ([pixel[0]; 3], 255),
+                    2 => ([pixel[0]; 3], pixel[1]),
+                    3 => ([pixel[0], pixel[1], pixel[2]], 255),
+                    _ => ([pixel[0], pixel[1], pixel[2]], pixel[3]),
+                };
+                if alpha == 0 {
+                    Tile::Floor
+                } else {
+                    Tile::from_rgb(rgb)
+                }
+            })
+            .collect();
+
+        Self::new(info.width as usize, info.height as usize, cell_size, tiles)
+    }
+
+    fn new(cols: usize, rows: usize, cell_size: f32, tiles: Vec<Tile>) -> Result<Self, MapError> {
+        if cols == 0 || rows == 0 {
+            return Err(MapError::Malformed("the map has no cells".to_string()));
+        }
+        if tiles.len() != cols * rows {
+            return Err(MapError::Malformed(format!(
+                "expected {} cells, found {}",
+                cols * rows,
+                tiles.len()
+            )));
+        }
+        Ok(Self {
+            cols,
+            rows,
+            cell_size,
+            tiles,
+        })
+    }
+
+    pub fn width(&self) -> f32 {
+        self.cols as f32 * self.cell_size
+    }
+
+    pub fn height(&self) -> f32 {
+        self.rows as f32 * self.cell_size
+    }
+
+    pub fn tile(&self, col: usize, row: usize) -> Tile {
+        self.tiles[row * self.cols + col]
+    }
+
+    /// Areas covered by `tile`. Neighbouring cells on a row are merged into one area.
+    pub fn zones_of(&self, tile: Tile) -> Vec<Zone> {
+        self.zones_where(|other| other == tile)
+    }
+
+    /// Areas covered by the tiles matching `predicate`, merged like in [MapLayout::zones_of]
+    pub fn zones_where(&self, predicate: impl Fn(Tile) -> bool) -> Vec<Zone> {
+        let mut zones = Vec::new();
+        for row in 0..self.rows {
+            let mut col = 0;
+            while col < self.cols {
+                if !predicate(self.tile(col, row)) {
+                    col += 1;
+                    continue;
+                }
+                let start = col;
+                while col < self.cols && predicate(self.tile(col, row)) {
+                    col += 1;
+                }
+                zones.push(Zone::new(
+                    [start as f32 * self.cell_size, row as f32 * self.cell_size],
+                    [
+                        col as f32 * self.cell_size,
+                        (row + 1) as f32 * self.cell_size,
+                    ],
+                ));
+            }
+        }
+        zones
+    }
+
+    /// Walls as obstacles blocking movement
+    pub fn walls(&self) -> Vec<Shape> {
+        self.zones_of(Tile::Wall)
+            .into_iter()
+            .map(|zone| Shape::Box {
+                min: zone.min,
+                max: zone.max,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MapLayout, Tile};
+
+    #[test]
+    fn test_text_map() {
+        let map = MapLayout::from_text("####\n#S.#\n#G~\n", 10.0).unwrap();
+
+        assert_eq!((map.cols, map.rows), (4, 3));
+        assert_eq!(map.width(), 40.0);
+        assert_eq!(map.tile(2, 2), Tile::Terrain('~' as u32));
+        assert_eq!(map.tile(3, 2), Tile::Floor);
+        // Walls next to each other on a row are merged into one box
+        assert_eq!(map.walls().len(), 4);
+        assert_eq!(map.zones_of(Tile::Spawn).len(), 1);
+    }
+}
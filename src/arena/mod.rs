@@ -0,0 +1,13 @@
+mod boundary;
+mod generate;
+mod map;
+mod obstacle;
+mod terrain;
+mod zone;
+
+pub use boundary::*;
+pub use generate::*;
+pub use map::*;
+pub use obstacle::*;
+pub use terrain::*;
+pub use zone::*;
@@ -0,0 +1,223 @@
+use crate::entities_components::Geo2D;
+
+/// The outline of a static obstacle in the arena
+#[derive(Clone, PartialEq, Debug)]
+pub enum Shape {
+    Circle {
+        center: Geo2D,
+        radius: f32,
+    },
+    /// Axis-aligned box
+    Box {
+        min: Geo2D,
+        max: Geo2D,
+    },
+    /// Line segment without thickness, players are kept their own radius away from it
+    Wall {
+        from: Geo2D,
+        to: Geo2D,
+    },
+}
+
+fn sub(a: &Geo2D, b: &Geo2D) -> Geo2D {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn dot(a: &Geo2D, b: &Geo2D) -> f32 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+fn cross(a: &Geo2D, b: &Geo2D) -> f32 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+fn length(a: &Geo2D) -> f32 {
+    f32::sqrt(dot(a, a))
+}
+
+/// Closest point to `point` on the segment from `from` to `to`
+fn closest_on_segment(point: &Geo2D, from: &Geo2D, to: &Geo2D) -> Geo2D {
+    let segment = sub(to, from);
+    let length2 = dot(&segment, &segment);
+    if length2 == 0.0 {
+        return *from;
+    }
+    let t = (dot(&sub(point, from), &segment) / length2).clamp(0.0, 1.0);
+    [from[0] + t * segment[0], from[1] + t * segment[1]]
+}
+
+/// Whether the segments `a` and `b` cross or touch
+fn segments_intersect(a: (&Geo2D, &Geo2D), b: (&Geo2D, &Geo2D)) -> bool {
+    let side = |from: &Geo2D, to: &Geo2D, point: &Geo2D| cross(&sub(to, from), &sub(point, from));
+    let on_segment = |from: &Geo2D, to: &Geo2D, point: &Geo2D| {
+        (0..2).all(|axis| {
+            f32::min(from[axis], to[axis]) <= point[axis]
+                && point[axis] <= f32::max(from[axis], to[axis])
+        })
+    };
+
+    let d1 = side(b.0, b.1, a.0);
+    let d2 = side(b.0, b.1, a.1);
+    let d3 = side(a.0, a.1, b.0);
+    let d4 = side(a.0, a.1, b.1);
+
+    if d1 * d2 < 0.0 && d3 * d4 < 0.0 {
+        return true;
+    }
+    (d1 == 0.0 && on_segment(b.0, b.1, a.0))
+        || (d2 == 0.0 && on_segment(b.0, b.1, a.1))
+        || (d3 == 0.0 && on_segment(a.0, a.1, b.0))
+        || (d4 == 0.0 && on_segment(a.0, a.1, b.1))
+}
+
+impl Shape {
+    /// Corners of the smallest axis-aligned box around the shape
+    pub fn bounds(&self) -> (Geo2D, Geo2D) {
+        match self {
+            Shape::Circle { center, radius } => (
+                [center[0] - radius, center[1] - radius],
+                [center[0] + radius, center[1] + radius],
+            ),
+            Shape::Box { min, max } => (*min, *max),
+            Shape::Wall { from, to } => (
+                [f32::min(from[0], to[0]), f32::min(from[1], to[1])],
+                [f32::max(from[0], to[0]), f32::max(from[1], to[1])],
+            ),
+        }
+    }
+
+    pub fn contains(&self, point: &Geo2D) -> bool {
+        match self {
+            Shape::Circle { center, radius } => length(&sub(point, center)) < *radius,
+            Shape::Box { min, max } => {
+                (0..2).all(|axis| min[axis] < point[axis] && point[axis] < max[axis])
+            }
+            Shape::Wall { .. } => false,
+        }
+    }
+
+    /// Distance from `point` to the shape, `0.0` if inside
+    pub fn distance(&self, point: &Geo2D) -> f32 {
+        match self {
+            Shape::Circle { center, radius } => f32::max(0.0, length(&sub(point, center)) - radius),
+            Shape::Box { min, max } => {
+                let closest = [
+                    point[0].clamp(min[0], max[0]),
+                    point[1].clamp(min[1], max[1]),
+                ];
+                length(&sub(point, &closest))
+            }
+            Shape::Wall { from, to } => length(&sub(point, &closest_on_segment(point, from, to))),
+        }
+    }
+
+    /// How a circle of `radius` at `point` overlaps the shape: the direction pushing it out,
+    /// and how far it has to be pushed. `None` if they do not overlap.
+    pub fn penetration(&self, point: &Geo2D, radius: f32) -> Option<(Geo2D, f32)> {
+        // Direction away from `from`, any direction will do if both are the same point
+        let away = |from: &Geo2D| {
+            let offset = sub(point, from);
+            let distance = length(&offset);
+            if distance > 0.0 {
+                ([offset[0] / distance, offset[1] / distance], distance)
+            } else {
+                ([1.0, 0.0], 0.0)
+            }
+        };
+
+        match self {
+            Shape::Circle {
+                center,
+                radius: obstacle_radius,
+            } => {
+                let (normal, distance) = away(center);
+                let depth = obstacle_radius + radius - distance;
+                Some((normal, depth)).filter(|_| depth > 0.0)
+            }
+            Shape::Box { min, max } if self.contains(point) => {
+                // Out through the closest face
+                let faces = [
+                    ([-1.0, 0.0], point[0] - min[0]),
+                    ([1.0, 0.0], max[0] - point[0]),
+                    ([0.0, -1.0], point[1] - min[1]),
+                    ([0.0, 1.0], max[1] - point[1]),
+                ];
+                faces
+                    .iter()
+                    .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+                    .map(|(normal, distance)| (*normal, distance + radius))
+            }
+            Shape::Box { min, max } => {
+                let closest = [
+                    point[0].clamp(min[0], max[0]),
+                    point[1].clamp(min[1], max[1]),
+                ];
+                let (normal, distance) = away(&closest);
+                Some((normal, radius - distance)).filter(|_| distance < radius)
+            }
+            Shape::Wall { from, to } => {
+                let (mut normal, distance) = away(&closest_on_segment(point, from, to));
+                if distance == 0.0 {
+                    // On the wall itself, push out sideways
+                    let along = sub(to, from);
+                    let along_length = length(&along);
+                    if along_length > 0.0 {
+                        normal = [-along[1] / along_length, along[0] / along_length];
+                    }
+                }
+                Some((normal, radius - distance)).filter(|_| distance < radius)
+            }
+        }
+    }
+
+    /// Whether the segment from `from` to `to` crosses the shape
+    pub fn intersects_segment(&self, from: &Geo2D, to: &Geo2D) -> bool {
+        match self {
+            Shape::Circle { center, radius } => {
+                length(&sub(center, &closest_on_segment(center, from, to))) < *radius
+            }
+            Shape::Box { min, max } => {
+                // Clip the segment against both slabs of the box
+                let direction = sub(to, from);
+                let (mut enter, mut exit) = (0.0f32, 1.0f32);
+                for axis in 0..2 {
+                    if direction[axis] == 0.0 {
+                        if from[axis] < min[axis] || from[axis] > max[axis] {
+                            return false;
+                        }
+                        continue;
+                    }
+                    let t1 = (min[axis] - from[axis]) / direction[axis];
+                    let t2 = (max[axis] - from[axis]) / direction[axis];
+                    enter = f32::max(enter, f32::min(t1, t2));
+                    exit = f32::min(exit, f32::max(t1, t2));
+                }
+                enter <= exit
+            }
+            Shape::Wall {
+                from: wall_from,
+                to: wall_to,
+            } => segments_intersect((from, to), (wall_from, wall_to)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Shape;
+
+    #[test]
+    fn test_wall_blocks_sight() {
+        let wall = Shape::Wall {
+            from: [50.0, 0.0],
+            to: [50.0, 100.0],
+        };
+
+        assert!(wall.intersects_segment(&[0.0, 50.0], &[100.0, 50.0]));
+        assert!(!wall.intersects_segment(&[0.0, 50.0], &[40.0, 90.0]));
+
+        let (normal, depth) = wall.penetration(&[47.0, 50.0], 10.0).unwrap();
+        assert_eq!(normal, [-1.0, 0.0]);
+        assert!((depth - 7.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,167 @@
+use shipyard::Component;
+
+use super::{Tile, Zone};
+use crate::entities_components::Position;
+
+/// Ground changing how players move and recover
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Terrain {
+    /// Players wade through at half speed
+    Mud,
+    /// Players only slowly turn to where they want to go
+    Ice,
+    /// Players regain endurance faster
+    Rest,
+}
+
+impl Terrain {
+    /// Brown (`#8B4513`) or `M` is mud, cyan (`#00FFFF`) or `I` is ice,
+    /// blue (`#0000FF`) or `R` is a rest zone
+    pub fn from_tile(tile: Tile) -> Option<Self> {
+        match tile {
+            Tile::Terrain(0x8B4513) => Some(Terrain::Mud),
+            Tile::Terrain(0x00FFFF) => Some(Terrain::Ice),
+            Tile::Terrain(0x0000FF) => Some(Terrain::Rest),
+            Tile::Terrain(code) => match std::char::from_u32(code) {
+                Some('M') => Some(Terrain::Mud),
+                Some('I') => Some(Terrain::Ice),
+                Some('R') => Some(Terrain::Rest),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Share of their velocity players move with
+    pub fn speed_factor(&self) -> f32 {
+        match self {
+            Terrain::Mud => 0.5,
+            Terrain::Ice | Terrain::Rest => 1.0,
+        }
+    }
+
+    /// Share of the turn a behaviour asks for which players manage in a tick
+    pub fn grip(&self) -> f32 {
+        match self {
+            Terrain::Ice => 0.1,
+            Terrain::Mud | Terrain::Rest => 1.0,
+        }
+    }
+
+    /// How many times more likely players are to regain endurance
+    pub fn regen_factor(&self) -> f64 {
+        match self {
+            Terrain::Rest => 3.0,
+            Terrain::Mud | Terrain::Ice => 1.0,
+        }
+    }
+}
+
+/// The terrain of the arena sampled in square cells, for cheap lookups every tick.
+/// Built once from [crate::SimConfig::terrain] when the `world` is initialized.
+#[derive(Clone, Default, Debug, Component)]
+pub struct TerrainLayer {
+    pub cell_size: f32,
+    pub cols: usize,
+    pub rows: usize,
+    /// Row-major, `None` for plain floor
+    pub cells: Vec<Option<Terrain>>,
+}
+
+impl TerrainLayer {
+    /// A cell takes the terrain of the last zone covering its centre
+    pub fn new(width: f32, height: f32, cell_size: f32, zones: &[(Zone, Terrain)]) -> Self {
+        let cols = (width / cell_size).ceil().max(1.0) as usize;
+        let rows = (height / cell_size).ceil().max(1.0) as usize;
+        let mut layer = Self {
+            cell_size,
+            cols,
+            rows,
+            cells: vec![None; cols * rows],
+        };
+        for cell in 0..layer.cells.len() {
+            let center = layer.center(cell);
+            layer.cells[cell] = zones
+                .iter()
+                .rev()
+                .find(|(zone, _)| zone.contains(&center))
+                .map(|(_, terrain)| *terrain);
+        }
+        layer
+    }
+
+    fn center(&self, cell: usize) -> Position {
+        Position::new(
+            ((cell % self.cols) as f32 + 0.5) * self.cell_size,
+            ((cell / self.cols) as f32 + 0.5) * self.cell_size,
+        )
+    }
+
+    /// The terrain under `pos`, `None` on plain floor or outside the arena
+    pub fn at(&self, pos: &Position) -> Option<Terrain> {
+        if self.cells.is_empty() || pos.0[0] < 0.0 || pos.0[1] < 0.0 {
+            return None;
+        }
+        let col = (pos.0[0] / self.cell_size) as usize;
+        let row = (pos.0[1] / self.cell_size) as usize;
+        if col >= self.cols || row >= self.rows {
+            return None;
+        }
+        self.cells[row * self.cols + col]
+    }
+
+    /// Centres of the cells with terrain within `radius` of `pos`
+    pub fn around(&self, pos: &Position, radius: f32) -> Vec<(Position, Terrain)> {
+        if self.cells.is_empty() {
+            return vec![];
+        }
+        let cell_range = |coord: f32, count: usize| {
+            let first = ((coord - radius) / self.cell_size).floor().max(0.0) as usize;
+            let last = ((coord + radius) / self.cell_size).floor().max(0.0) as usize;
+            first..=last.min(count - 1)
+        };
+
+        let mut found = Vec::new();
+        for row in cell_range(pos.0[1], self.rows) {
+            for col in cell_range(pos.0[0], self.cols) {
+                let cell = row * self.cols + col;
+                let center = self.center(cell);
+                if let Some(terrain) = self.cells[cell] {
+                    if center.distance_to(pos) <= radius {
+                        found.push((center, terrain));
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Terrain, TerrainLayer};
+    use crate::arena::Zone;
+    use crate::entities_components::Position;
+
+    #[test]
+    fn test_terrain_lookup() {
+        let layer = TerrainLayer::new(
+            100.0,
+            100.0,
+            10.0,
+            &[
+                (Zone::new([0.0, 0.0], [50.0, 100.0]), Terrain::Mud),
+                (Zone::new([40.0, 40.0], [60.0, 60.0]), Terrain::Rest),
+            ],
+        );
+
+        assert_eq!(layer.at(&Position::new(5.0, 5.0)), Some(Terrain::Mud));
+        // Later zones cover earlier ones
+        assert_eq!(layer.at(&Position::new(45.0, 45.0)), Some(Terrain::Rest));
+        assert_eq!(layer.at(&Position::new(75.0, 5.0)), None);
+        assert!(layer
+            .around(&Position::new(75.0, 50.0), 25.0)
+            .iter()
+            .any(|(_, terrain)| *terrain == Terrain::Rest));
+    }
+}
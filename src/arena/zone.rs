@@ -0,0 +1,51 @@
+use rand::distributions::Distribution;
+use rand::Rng;
+
+use crate::entities_components::{Geo2D, Position};
+
+/// An axis-aligned area of the arena
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Zone {
+    pub min: Geo2D,
+    pub max: Geo2D,
+}
+
+impl Zone {
+    pub fn new(min: Geo2D, max: Geo2D) -> Self {
+        Self { min, max }
+    }
+
+    pub fn contains(&self, pos: &Position) -> bool {
+        (0..2).all(|axis| self.min[axis] <= pos.0[axis] && pos.0[axis] < self.max[axis])
+    }
+
+    /// The zone scaled by `scale` around its centre
+    pub fn scaled(&self, scale: f32) -> Self {
+        let center = self.center().0;
+        let half = [
+            (self.max[0] - self.min[0]) / 2.0 * scale,
+            (self.max[1] - self.min[1]) / 2.0 * scale,
+        ];
+        Self::new(
+            [center[0] - half[0], center[1] - half[1]],
+            [center[0] + half[0], center[1] + half[1]],
+        )
+    }
+
+    pub fn center(&self) -> Position {
+        Position::new(
+            (self.min[0] + self.max[0]) / 2.0,
+            (self.min[1] + self.max[1]) / 2.0,
+        )
+    }
+}
+
+/// Positions spread uniformly over the zone
+impl Distribution<Position> for Zone {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Position {
+        Position::new(
+            rng.gen_range(self.min[0]..self.max[0]),
+            rng.gen_range(self.min[1]..self.max[1]),
+        )
+    }
+}
@@ -0,0 +1,175 @@
+use shipyard::Component;
+
+use crate::entities_components::{Position, TagState, Velocity};
+use crate::spatial::Topology;
+
+use super::{BehaviourAction, BehaviourContext, Steering};
+
+/// How strongly a flocking player follows each of the flocking rules, and how far it looks.
+/// Every player starts with [crate::SimConfig::flocking], players can be given their own
+/// weights by changing their component.
+#[derive(Clone, Debug, PartialEq, Component)]
+pub struct FlockWeights {
+    /// Keeping clear of flockmates closer than `separation_radius`
+    pub separation: f32,
+    /// Heading the same way as the flockmates
+    pub alignment: f32,
+    /// Moving towards the middle of the flockmates
+    pub cohesion: f32,
+    /// Untagged players within this distance are flockmates
+    pub neighbour_radius: f32,
+    pub separation_radius: f32,
+    /// "It" players closer than this are run from, before anything else
+    pub panic_distance: f32,
+}
+
+impl Default for FlockWeights {
+    fn default() -> Self {
+        Self {
+            separation: 1.5,
+            alignment: 1.0,
+            cohesion: 1.0,
+            neighbour_radius: 80.0,
+            separation_radius: 25.0,
+            panic_distance: 150.0,
+        }
+    }
+}
+
+/// The "not it" player moves with the other untagged players around it, as a boid
+/// in a flock. Running from "it" players coming close comes first, the flock is only
+/// followed while no threat is in sight. The weights of the rules are the player's own [FlockWeights].
+#[derive(Debug)]
+pub struct BoidsBehaviour;
+
+impl BehaviourAction for BoidsBehaviour {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let weights = ctx.flock_weights;
+        let my_pos = ctx.current_player.0;
+        let entity_id = ctx.entity_id;
+
+        let neighbours = ctx.players.within_radius(my_pos, weights.neighbour_radius);
+        let flockmates: Vec<(&Position, &Velocity)> = neighbours
+            .iter()
+            .filter(|n| n.entity_id != entity_id)
+            .filter(|n| n.tagged.0 == TagState::NotIt || n.tagged.0 == TagState::Recovered)
+            .map(|n| (&n.position, &n.velocity))
+            .collect();
+        let threat = ctx
+            .nearest_5_neighbors
+            .iter()
+            .find(|n| n.tagged.0 == TagState::It)
+            .map(|n| &n.position);
+
+        flock(weights, &ctx.topology, my_pos, &flockmates, threat)
+    }
+}
+
+/// The steering of a boid at `my_pos` among its `flockmates`, given as their positions
+/// and velocities. Away from the closest `threat` instead, while it is within the panic distance.
+pub fn flock(
+    weights: &FlockWeights,
+    topology: &Topology,
+    my_pos: &Position,
+    flockmates: &[(&Position, &Velocity)],
+    threat: Option<&Position>,
+) -> Steering {
+    if let Some(threat) = threat {
+        let distance = topology.distance(my_pos, threat);
+        if distance > 0.0 && distance < weights.panic_distance {
+            return Steering::towards(topology.direction(threat, my_pos));
+        }
+    }
+
+    let mut rules = Vec::with_capacity(3);
+
+    if !flockmates.is_empty() {
+        let count = flockmates.len() as f32;
+        let mut push = [0.0, 0.0];
+        let mut heading = [0.0, 0.0];
+        let mut middle = [0.0, 0.0];
+
+        for (pos, vel) in flockmates {
+            let [dx, dy] = topology.offset(&my_pos.0, &pos.0);
+            let distance_squared = dx * dx + dy * dy;
+            if distance_squared > 0.0
+                && distance_squared < weights.separation_radius * weights.separation_radius
+            {
+                // Away from the flockmate, by the inverse of the distance
+                push[0] -= dx / distance_squared;
+                push[1] -= dy / distance_squared;
+            }
+            if vel.magnitude() > 0.0 {
+                let vel = vel.normalize();
+                heading[0] += vel.0[0];
+                heading[1] += vel.0[1];
+            }
+            middle[0] += dx / count;
+            middle[1] += dy / count;
+        }
+
+        rules.push((weights.separation, Velocity::new(push[0], push[1])));
+        rules.push((weights.alignment, Velocity::new(heading[0], heading[1])));
+        rules.push((weights.cohesion, Velocity::new(middle[0], middle[1])));
+    }
+
+    // Rules without any direction, e.g. no flockmate being too close, are left out
+    let steerings: Vec<(f32, Steering)> = rules
+        .into_iter()
+        .filter(|(weight, direction)| *weight > 0.0 && direction.magnitude() > 0.0)
+        .map(|(weight, direction)| (weight, Steering::towards(direction)))
+        .collect();
+    Steering::blend(&steerings)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{flock, FlockWeights};
+    use crate::arena::BoundaryPolicy;
+    use crate::entities_components::{Position, Velocity};
+    use crate::spatial::Topology;
+
+    #[test]
+    fn test_flocking_rules() {
+        let weights = FlockWeights::default();
+        let topology = Topology::for_policy(1000.0, 1000.0, BoundaryPolicy::Bounce);
+        let me = Position::new(500.0, 500.0);
+        let mate = Position::new(550.0, 500.0);
+        let going_up = Velocity::new(0.0, 2.0);
+
+        // Alone the player keeps its course
+        assert!(flock(&weights, &topology, &me, &[], None).is_idle());
+
+        // Towards the flockmate on the right, and along with it
+        let direction = flock(&weights, &topology, &me, &[(&mate, &going_up)], None)
+            .direction
+            .unwrap();
+        assert!(direction.0[0] > 0.0 && direction.0[1] > 0.0);
+
+        // An "it" player on the right, just within the panic distance, is run from straight away
+        let threat = Position::new(500.0 + weights.panic_distance - 1.0, 500.0);
+        let direction = flock(
+            &weights,
+            &topology,
+            &me,
+            &[(&mate, &going_up)],
+            Some(&threat),
+        )
+        .direction
+        .unwrap();
+        assert!((direction.0[0] + 1.0).abs() < 1e-6 && direction.0[1].abs() < 1e-6);
+
+        // Just out of the panic distance, the flock is followed again
+        let threat = Position::new(500.0 + weights.panic_distance + 1.0, 500.0);
+        let direction = flock(
+            &weights,
+            &topology,
+            &me,
+            &[(&mate, &going_up)],
+            Some(&threat),
+        )
+        .direction
+        .unwrap();
+        assert!(direction.0[0] > 0.0 && direction.0[1] > 0.0);
+    }
+}
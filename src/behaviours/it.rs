@@ -1,12 +1,20 @@
 use rand::Rng;
 
-use super::{BehaviourAction, BehaviourContext};
+use crate::entities_components::TagState;
+
+use super::{
+    BehaviourAction, BehaviourContext, PursueBehaviour, SeekBehaviour, Steering, WanderBehaviour,
+};
 
 /// When an actor is "it" they can behave in these states.
 #[derive(Debug)]
 pub enum ItBehaviour {
     RandomBehaviour(RandomBehaviour),
     ChaseNearest(ChaseNearestBehaviour),
+    GuardFlag(GuardFlagBehaviour),
+    Seek(SeekBehaviour),
+    Pursue(PursueBehaviour),
+    Wander(WanderBehaviour),
 }
 
 impl Default for ItBehaviour {
@@ -16,11 +24,15 @@ impl Default for ItBehaviour {
 }
 
 impl BehaviourAction for ItBehaviour {
-    fn revise_orientation(&self, ctx: BehaviourContext) {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
         match self {
-            ItBehaviour::ChaseNearest(b) => b.revise_orientation(ctx),
-            ItBehaviour::RandomBehaviour(b) => b.revise_orientation(ctx),
-        };
+            ItBehaviour::ChaseNearest(b) => b.steer(ctx),
+            ItBehaviour::RandomBehaviour(b) => b.steer(ctx),
+            ItBehaviour::GuardFlag(b) => b.steer(ctx),
+            ItBehaviour::Seek(b) => b.steer(ctx),
+            ItBehaviour::Pursue(b) => b.steer(ctx),
+            ItBehaviour::Wander(b) => b.steer(ctx),
+        }
     }
 }
 
@@ -29,37 +41,61 @@ impl BehaviourAction for ItBehaviour {
 pub struct RandomBehaviour;
 
 impl BehaviourAction for RandomBehaviour {
-    fn revise_orientation(&self, ctx: BehaviourContext) {
-        let mut rng = rand::thread_rng();
-        let vel = ctx.current_player.1;
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let rng = &mut ctx.rng.0;
 
         // Every now and then the player's direction changes
         if rng.gen_bool(0.005) {
-            *vel = vel.rotate_angle(rng.gen_range(0.0..std::f32::consts::TAU))
+            Steering::turn(rng.gen_range(0.0..std::f32::consts::TAU))
+        } else {
+            Steering::default()
         }
     }
 }
 
 /// The tagged "it" player will try to "cut corners" and predict movement of its closest neighbour.
+/// Neighbours out of sight are chased along a path around the obstacles.
 #[derive(Debug)]
 pub struct ChaseNearestBehaviour;
 
 impl BehaviourAction for ChaseNearestBehaviour {
-    fn revise_orientation(&self, ctx: BehaviourContext) {
-        if let Some(near) = ctx.nearest_5_neighbors.iter().find(|n| !n.recently_tagged) {
-            let my_pos = ctx.current_player.0;
-            if my_pos.distance_to(&near.position) > 0.5 {
-                let my_vel = ctx.current_player.1;
-                let near_pos = &near.position;
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let near_pos = ctx
+            .nearest_5_neighbors
+            .iter()
+            .find(|n| n.tagged.0 == TagState::NotIt && !n.recently_tagged)
+            .map(|n| n.position.clone());
 
-                let v_target = my_pos.velocity_facing(near_pos);
-                let angle = my_vel.angle_between(&v_target);
-
-                let new_vel = my_vel.rotate_angle(angle);
-                *my_vel = new_vel;
-            }
-        } else {
-            RandomBehaviour::revise_orientation(&RandomBehaviour, ctx);
+        match near_pos {
+            Some(near_pos) => ctx.head_for(&near_pos),
+            None => RandomBehaviour.steer(ctx),
         }
     }
 }
+
+/// In capture the flag, the player chases the closest enemy close to its flag,
+/// and otherwise heads back to the flag to guard it.
+#[derive(Debug)]
+pub struct GuardFlagBehaviour;
+
+/// How far from its flag a guard chases intruders
+const GUARD_RADIUS: f32 = 200.0;
+
+impl BehaviourAction for GuardFlagBehaviour {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let own_flag = match ctx.objectives.own_flag.clone() {
+            Some(own_flag) => own_flag,
+            None => return ChaseNearestBehaviour.steer(ctx),
+        };
+        let intruder = ctx
+            .nearest_5_neighbors
+            .iter()
+            .filter(|n| n.team.is_some() && n.team != ctx.team)
+            .filter(|n| n.tagged.0 != TagState::Jailed && !n.recently_tagged)
+            .find(|n| ctx.topology.distance(&own_flag, &n.position) < GUARD_RADIUS)
+            .map(|n| n.position.clone());
+        let target = intruder.unwrap_or(own_flag);
+
+        ctx.head_for(&target)
+    }
+}
@@ -1,22 +1,99 @@
+mod boids;
 mod it;
 mod not_it;
+mod registry;
+mod reynolds;
+mod steering;
+mod tree;
 
-use crate::entities_components::RTreeData;
+use shipyard::EntityId;
+
+use crate::arena::{BoundaryPolicy, Shape, TerrainLayer, Zone};
+use crate::entities_components::{Endurance, PlayerRng, RTreeData};
+use crate::navigation::Navigator;
+use crate::spatial::{ObstacleQuery, SpatialQuery, Topology};
 
 use super::{Position, Velocity};
+pub use boids::*;
 pub use it::*;
 pub use not_it::*;
+pub use registry::*;
+pub use reynolds::*;
+pub use steering::*;
+pub use tree::*;
 
 /// In order to evaluate the behaviour of a player, some information about
 /// the player's environment is passed down to the bahviour specifications.
 pub struct BehaviourContext<'a> {
-    pub current_player: (&'a Position, &'a mut Velocity),
+    pub entity_id: EntityId,
+    pub current_player: (&'a Position, &'a Velocity),
     pub distance_to_it: f32,
+    /// Closest players first, measured along the player's [Topology]
     pub nearest_5_neighbors: Vec<&'a RTreeData>,
+    /// Every player in the arena, to look for neighbours further away
+    pub players: SpatialQuery<'a>,
+    /// How the arena edges treat the player in its current tag state
+    pub boundary: BoundaryPolicy,
+    /// Distances and directions as the player can travel them, across edges if it can wrap
+    pub topology: Topology,
+    /// The player's own random stream, behaviours must not use any other source of randomness
+    pub rng: &'a mut PlayerRng,
+    /// The player's team, when playing in teams
+    pub team: Option<usize>,
+    /// Places the player's team is interested in
+    pub objectives: Objectives,
+    /// Obstacles in the arena, to check what the player can see
+    pub obstacles: ObstacleQuery<'a>,
+    /// Paths around the obstacles, cached for the player between ticks
+    pub navigation: Navigator<'a>,
+    /// Ground in the arena, to check what lies under and around the player
+    pub terrain: &'a TerrainLayer,
+    /// How rested the player is
+    pub endurance: &'a Endurance,
+    /// Bases where the player cannot be tagged
+    pub bases: &'a [Shape],
+    /// The part of the arena currently in play, players outside of it are penalized
+    pub play_area: Zone,
+    /// How the player moves in a flock
+    pub flock_weights: &'a FlockWeights,
+    /// What the player remembers between ticks
+    pub blackboard: &'a mut Blackboard,
+}
+
+impl<'a> BehaviourContext<'a> {
+    /// Steer the player towards `target`, along a path around the obstacles if it is out of sight
+    pub fn head_for(&mut self, target: &Position) -> Steering {
+        let my_pos = self.current_player.0;
+        let waypoint = if self.obstacles.line_of_sight(my_pos, target) {
+            target.clone()
+        } else {
+            self.navigation.next_waypoint(my_pos, target)
+        };
+
+        if self.topology.distance(my_pos, &waypoint) > 0.5 {
+            Steering::towards(self.topology.direction(my_pos, &waypoint))
+        } else {
+            Steering::default()
+        }
+    }
+}
+
+/// Positions which matter to a team, left empty when not playing in teams
+#[derive(Clone, Default, Debug)]
+pub struct Objectives {
+    /// The flag of the player's own team, wherever it currently is
+    pub own_flag: Option<Position>,
+    /// Flags of the other teams which nobody carries yet
+    pub enemy_flags: Vec<Position>,
+    /// Where a captured flag has to be brought
+    pub home: Option<Position>,
+    /// Whether the player is carrying an enemy flag
+    pub carrying_flag: bool,
 }
 
-/// A behaviour defines how an actor orients itself in accordance to the current
-/// environment
+/// A behaviour defines how an actor wants to move in accordance to the current
+/// environment. It only asks through its [Steering], the player's [Velocity] is
+/// changed afterwards within the limits of the player's movement.
 pub trait BehaviourAction {
-    fn revise_orientation(&self, ctx: BehaviourContext);
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering;
 }
@@ -1,14 +1,27 @@
 use rand::Rng;
 
-use crate::entities_components::TagState;
+use crate::arena::Shape;
+use crate::entities_components::{Endurance, Position, TagState};
 
-use super::{BehaviourAction, BehaviourContext};
+use super::{
+    ArriveBehaviour, BehaviourAction, BehaviourContext, BoidsBehaviour, EvadeBehaviour,
+    FleeBehaviour, SeparationBehaviour, Steering, WanderBehaviour,
+};
 
 /// When an actor is "not it" they can behave in these states.
 #[derive(Debug)]
 pub enum NotItBehaviour {
     OpposeIt(OpposeItBehaviour),
     Random(RandomBehaviour),
+    RescueFrozen(RescueFrozenBehaviour),
+    CaptureFlag(CaptureFlagBehaviour),
+    SeekBase(SeekBaseBehaviour),
+    Flee(FleeBehaviour),
+    Evade(EvadeBehaviour),
+    Arrive(ArriveBehaviour),
+    Wander(WanderBehaviour),
+    Separate(SeparationBehaviour),
+    Boids(BoidsBehaviour),
 }
 
 impl Default for NotItBehaviour {
@@ -18,11 +31,20 @@ impl Default for NotItBehaviour {
 }
 
 impl BehaviourAction for NotItBehaviour {
-    fn revise_orientation(&self, ctx: BehaviourContext) {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
         match self {
-            NotItBehaviour::OpposeIt(b) => b.revise_orientation(ctx),
-            NotItBehaviour::Random(b) => b.revise_orientation(ctx),
-        };
+            NotItBehaviour::OpposeIt(b) => b.steer(ctx),
+            NotItBehaviour::Random(b) => b.steer(ctx),
+            NotItBehaviour::RescueFrozen(b) => b.steer(ctx),
+            NotItBehaviour::CaptureFlag(b) => b.steer(ctx),
+            NotItBehaviour::SeekBase(b) => b.steer(ctx),
+            NotItBehaviour::Flee(b) => b.steer(ctx),
+            NotItBehaviour::Evade(b) => b.steer(ctx),
+            NotItBehaviour::Arrive(b) => b.steer(ctx),
+            NotItBehaviour::Wander(b) => b.steer(ctx),
+            NotItBehaviour::Separate(b) => b.steer(ctx),
+            NotItBehaviour::Boids(b) => b.steer(ctx),
+        }
     }
 }
 
@@ -31,41 +53,174 @@ impl BehaviourAction for NotItBehaviour {
 pub struct RandomBehaviour;
 
 impl BehaviourAction for RandomBehaviour {
-    fn revise_orientation(&self, ctx: BehaviourContext) {
-        let mut rng = rand::thread_rng();
-        let vel = ctx.current_player.1;
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let rng = &mut ctx.rng.0;
 
         // Every now and then the player's direction changes
         if rng.gen_bool(0.02) {
-            *vel = vel.rotate_angle(rng.gen_range(0.0..std::f32::consts::PI))
+            Steering::turn(rng.gen_range(0.0..std::f32::consts::PI))
+        } else {
+            Steering::default()
         }
     }
 }
 
-/// The "not it" players with this behaviour will be averse to the currently tagged actor.
+/// The "not it" players with this behaviour will be averse to the currently tagged actor,
+/// as long as they can see it. When an obstacle is in the way of running straight away,
+/// they find a path to an open spot on the far side from "it".
 #[derive(Debug)]
 pub struct OpposeItBehaviour;
 
+/// How far ahead a fleeing player looks for a safe spot
+const FLEE_DISTANCE: f32 = 100.0;
+
 impl BehaviourAction for OpposeItBehaviour {
-    fn revise_orientation(&self, ctx: BehaviourContext) {
-        if let Some(near) = ctx
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let my_pos = ctx.current_player.0;
+        let near_pos = ctx
             .nearest_5_neighbors
             .iter()
-            .find(|n| n.tagged.0 == TagState::It)
-        {
-            let my_pos = ctx.current_player.0;
-            if my_pos.distance_to(&near.position) > 0.5 {
-                let my_vel = ctx.current_player.1;
-                let near_pos = &near.position;
-
-                let v_target = my_pos.velocity_facing(near_pos);
-                let angle = my_vel.angle_between(&v_target);
-
-                let new_vel = my_vel.rotate_angle(angle).negate_velocity();
-                *my_vel = new_vel;
-            }
+            .find(|n| {
+                n.tagged.0 == TagState::It && ctx.obstacles.line_of_sight(my_pos, &n.position)
+            })
+            .map(|n| n.position.clone());
+
+        let near_pos = match near_pos {
+            Some(near_pos) => near_pos,
+            None => return RandomBehaviour.steer(ctx),
+        };
+        if ctx.topology.distance(my_pos, &near_pos) <= 0.5 {
+            return Steering::default();
+        }
+
+        let away = ctx.topology.direction(&near_pos, my_pos);
+        let safe_spot = Position::new(
+            my_pos.0[0] + away.0[0] * FLEE_DISTANCE,
+            my_pos.0[1] + away.0[1] * FLEE_DISTANCE,
+        );
+        if ctx.obstacles.line_of_sight(my_pos, &safe_spot) {
+            Steering::towards(away)
         } else {
-            RandomBehaviour::revise_orientation(&RandomBehaviour, ctx);
+            let safe_spot = ctx
+                .navigation
+                .grid
+                .nearest_open(&safe_spot)
+                .unwrap_or(safe_spot);
+            ctx.head_for(&safe_spot)
+        }
+    }
+}
+
+/// In freeze tag, the player heads for the nearest frozen teammate within [RESCUE_DISTANCE]
+/// to free them. While nobody is frozen that close, or an "it" player comes within
+/// [RESCUE_DANGER_DISTANCE], it keeps away from "it".
+#[derive(Debug)]
+pub struct RescueFrozenBehaviour;
+
+/// How far a player goes to free a frozen teammate
+pub const RESCUE_DISTANCE: f32 = 250.0;
+/// Rescues are given up for "it" players closer than this
+pub const RESCUE_DANGER_DISTANCE: f32 = 80.0;
+
+impl BehaviourAction for RescueFrozenBehaviour {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let my_pos = ctx.current_player.0;
+        let it_near = ctx
+            .players
+            .within_radius(my_pos, RESCUE_DANGER_DISTANCE)
+            .iter()
+            .any(|n| n.tagged.0 == TagState::It);
+        // Neighbours are sorted by distance, so the first frozen one is the closest
+        let frozen_pos = ctx
+            .nearest_5_neighbors
+            .iter()
+            .find(|n| n.tagged.0 == TagState::Frozen)
+            .map(|n| n.position.clone())
+            .filter(|pos| ctx.topology.distance(my_pos, pos) <= RESCUE_DISTANCE);
+
+        match frozen_pos {
+            Some(frozen_pos) if !it_near => ctx.head_for(&frozen_pos),
+            _ => OpposeItBehaviour.steer(ctx),
+        }
+    }
+}
+
+/// In capture the flag, the player brings a carried flag home, frees jailed teammates close by,
+/// and otherwise heads for the closest enemy flag.
+#[derive(Debug)]
+pub struct CaptureFlagBehaviour;
+
+impl BehaviourAction for CaptureFlagBehaviour {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let my_pos = ctx.current_player.0;
+        let jailed_teammate = ctx
+            .nearest_5_neighbors
+            .iter()
+            .find(|n| n.team == ctx.team && n.tagged.0 == TagState::Jailed)
+            .map(|n| n.position.clone());
+        let closest_flag = ctx
+            .objectives
+            .enemy_flags
+            .iter()
+            .min_by(|a, b| {
+                let a = ctx.topology.distance(my_pos, a);
+                let b = ctx.topology.distance(my_pos, b);
+                a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .cloned();
+
+        let target = if ctx.objectives.carrying_flag {
+            ctx.objectives.home.clone()
+        } else {
+            jailed_teammate.or(closest_flag)
+        };
+
+        match target {
+            Some(target) => ctx.head_for(&target),
+            None => RandomBehaviour.steer(ctx),
+        }
+    }
+}
+
+/// The player runs to the nearest base when an "it" player comes close,
+/// and stays in it until rested. Otherwise it keeps away from "it" like [OpposeItBehaviour].
+#[derive(Debug)]
+pub struct SeekBaseBehaviour;
+
+/// How close an "it" player has to be for the player to run for a base
+const THREAT_DISTANCE: f32 = 150.0;
+/// Share of the max endurance at which the player leaves the base
+const RESTED: f32 = 0.9;
+
+impl BehaviourAction for SeekBaseBehaviour {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let my_pos = ctx.current_player.0;
+        let threatened = ctx.nearest_5_neighbors.iter().any(|n| {
+            n.tagged.0 == TagState::It
+                && ctx.topology.distance(my_pos, &n.position) < THREAT_DISTANCE
+        });
+        let Endurance(current, max) = *ctx.endurance;
+        let rested = current as f32 >= max as f32 * RESTED;
+        let in_base = ctx.bases.iter().any(|base| base.contains(&my_pos.0));
+
+        // Bases are headed for through their middle, players in a base circle around it
+        let middle = |base: &Shape| {
+            let (min, max) = base.bounds();
+            Position::new((min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0)
+        };
+        let nearest_base = ctx
+            .bases
+            .iter()
+            .min_by(|a, b| {
+                let a = a.distance(&my_pos.0);
+                let b = b.distance(&my_pos.0);
+                a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(middle);
+
+        match nearest_base {
+            Some(base) if (threatened && !in_base) || (in_base && !rested) => ctx.head_for(&base),
+            _ => OpposeItBehaviour.steer(ctx),
         }
     }
 }
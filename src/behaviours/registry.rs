@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use shipyard::Component;
+
+use super::*;
+
+/// A behaviour shared by every player referring to it by name
+pub type SharedBehaviour = Arc<dyn BehaviourAction + Send + Sync>;
+
+/// Behaviours players can take on, by name. Players refer to them through their
+/// [crate::PlayerBehaviour], so strategies from other crates can be plugged in with
+/// [BehaviourRegistry::register] without changing this one.
+/// The registry is a unique of the `world`, see [crate::initialize_world_with_registry].
+#[derive(Clone, Component)]
+pub struct BehaviourRegistry {
+    behaviours: HashMap<String, SharedBehaviour>,
+}
+
+impl BehaviourRegistry {
+    /// A registry without any behaviour, not even the built-in ones
+    pub fn empty() -> Self {
+        Self {
+            behaviours: HashMap::new(),
+        }
+    }
+
+    /// Add `behaviour` under `name`, replacing any behaviour registered with the same name
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        behaviour: impl BehaviourAction + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.behaviours.insert(name.into(), Arc::new(behaviour));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SharedBehaviour> {
+        self.behaviours.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.behaviours.contains_key(name)
+    }
+
+    /// Registered names, sorted
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.behaviours.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+}
+
+/// A behaviour players are meant to take on, which is not in the [BehaviourRegistry]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownBehaviour(pub String);
+
+impl fmt::Display for UnknownBehaviour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "behaviour `{}` is not registered", self.0)
+    }
+}
+
+impl std::error::Error for UnknownBehaviour {}
+
+/// The built-in behaviours
+impl Default for BehaviourRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry
+            .register(
+                "chase_nearest",
+                ItBehaviour::ChaseNearest(ChaseNearestBehaviour),
+            )
+            .register(
+                "random_it",
+                ItBehaviour::RandomBehaviour(super::it::RandomBehaviour),
+            )
+            .register("guard_flag", ItBehaviour::GuardFlag(GuardFlagBehaviour))
+            .register("seek", ItBehaviour::Seek(SeekBehaviour::default()))
+            .register("pursue", ItBehaviour::Pursue(PursueBehaviour::default()))
+            .register("wander_it", ItBehaviour::Wander(WanderBehaviour::default()))
+            .register("oppose_it", NotItBehaviour::OpposeIt(OpposeItBehaviour))
+            .register(
+                "random",
+                NotItBehaviour::Random(super::not_it::RandomBehaviour),
+            )
+            .register(
+                "rescue_frozen",
+                NotItBehaviour::RescueFrozen(RescueFrozenBehaviour),
+            )
+            .register(
+                "capture_flag",
+                NotItBehaviour::CaptureFlag(CaptureFlagBehaviour),
+            )
+            .register("seek_base", NotItBehaviour::SeekBase(SeekBaseBehaviour))
+            .register("flee", NotItBehaviour::Flee(FleeBehaviour::default()))
+            .register("evade", NotItBehaviour::Evade(EvadeBehaviour::default()))
+            .register(
+                "arrive_base",
+                NotItBehaviour::Arrive(ArriveBehaviour::default()),
+            )
+            .register("wander", NotItBehaviour::Wander(WanderBehaviour::default()))
+            .register(
+                "separate",
+                NotItBehaviour::Separate(SeparationBehaviour::default()),
+            )
+            .register("boids", NotItBehaviour::Boids(BoidsBehaviour));
+        registry
+    }
+}
+
+impl fmt::Debug for BehaviourRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.names()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use shipyard::UniqueView;
+
+    use super::{BehaviourAction, BehaviourContext, BehaviourRegistry, Steering, UnknownBehaviour};
+    use crate::{initialize_world_with_registry, SimConfig, UnresolvedBehaviours, TICK};
+
+    struct Counting(Arc<AtomicUsize>);
+
+    impl BehaviourAction for Counting {
+        fn steer(&self, _ctx: &mut BehaviourContext) -> Steering {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Steering::default()
+        }
+    }
+
+    #[test]
+    fn test_registered_behaviour_is_used() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut registry = BehaviourRegistry::default();
+        registry.register("counting", Counting(calls.clone()));
+
+        let world = initialize_world_with_registry(
+            SimConfig {
+                player_count: 10,
+                not_it_behaviour: Some("counting".to_string()),
+                ..SimConfig::default()
+            },
+            registry,
+        );
+        world.run_workload(TICK).unwrap();
+
+        // Every player but the one "it" player
+        assert_eq!(calls.load(Ordering::Relaxed), 9);
+    }
+
+    #[test]
+    fn test_unregistered_behaviour_is_counted() {
+        let config = SimConfig {
+            player_count: 10,
+            not_it_behaviour: Some("teleport".to_string()),
+            ..SimConfig::default()
+        };
+        let registry = BehaviourRegistry::default();
+        assert_eq!(
+            config.check_behaviours(&registry),
+            Err(UnknownBehaviour("teleport".to_string()))
+        );
+
+        let world = initialize_world_with_registry(config, registry);
+        world.run_workload(TICK).unwrap();
+
+        // Every player but the one "it" player kept its course
+        assert_eq!(
+            world
+                .borrow::<UniqueView<UnresolvedBehaviours>>()
+                .unwrap()
+                .0,
+            9
+        );
+    }
+}
@@ -0,0 +1,545 @@
+use rand::Rng;
+
+use crate::entities_components::{Position, TagState, Velocity};
+
+use super::{BehaviourAction, BehaviourContext, Steering};
+
+/// What a steering behaviour moves towards or away from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SteeringTarget {
+    /// The closest player "it" can tag
+    Prey,
+    /// The closest "it" player
+    It,
+    /// The middle of the closest base
+    Base,
+    /// Where the player's team brings captured flags
+    Home,
+}
+
+impl SteeringTarget {
+    /// Where the target is and how it moves, if there is one
+    fn locate(&self, ctx: &BehaviourContext) -> Option<(Position, Velocity)> {
+        let my_pos = ctx.current_player.0;
+        let still = Velocity::new(0.0, 0.0);
+
+        match self {
+            SteeringTarget::Prey => ctx
+                .nearest_5_neighbors
+                .iter()
+                .find(|n| n.tagged.0 == TagState::NotIt && !n.recently_tagged)
+                .map(|n| (n.position.clone(), n.velocity.clone())),
+            SteeringTarget::It => ctx
+                .nearest_5_neighbors
+                .iter()
+                .find(|n| n.tagged.0 == TagState::It)
+                .map(|n| (n.position.clone(), n.velocity.clone())),
+            SteeringTarget::Base => ctx
+                .bases
+                .iter()
+                .min_by(|a, b| {
+                    let a = a.distance(&my_pos.0);
+                    let b = b.distance(&my_pos.0);
+                    a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .map(|base| {
+                    let (min, max) = base.bounds();
+                    let middle = Position::new((min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0);
+                    (middle, still)
+                }),
+            SteeringTarget::Home => ctx.objectives.home.clone().map(|home| (home, still)),
+        }
+    }
+}
+
+/// Where a target at `position` moving at `velocity` will be by the time a player `distance`
+/// away, running at `speed`, gets there. Looks at most `max_prediction` ticks ahead.
+pub fn predict_position(
+    position: &Position,
+    velocity: &Velocity,
+    distance: f32,
+    speed: f32,
+    max_prediction: f32,
+) -> Position {
+    let ticks = if speed > 0.0 {
+        (distance / speed).min(max_prediction)
+    } else {
+        max_prediction
+    };
+    Position::new(
+        position.0[0] + velocity.0[0] * ticks,
+        position.0[1] + velocity.0[1] * ticks,
+    )
+}
+
+/// Speed at which a player `distance` away from its target comes to a stop on it,
+/// slowing down from `max_speed` once within `slowing_radius`
+pub fn arrival_speed(distance: f32, max_speed: f32, slowing_radius: f32) -> f32 {
+    if slowing_radius > 0.0 {
+        max_speed * (distance / slowing_radius).min(1.0)
+    } else {
+        max_speed
+    }
+}
+
+/// The player heads straight for where its target is now.
+#[derive(Clone, Debug)]
+pub struct SeekBehaviour {
+    pub target: SteeringTarget,
+}
+
+impl Default for SeekBehaviour {
+    fn default() -> Self {
+        Self {
+            target: SteeringTarget::Prey,
+        }
+    }
+}
+
+impl BehaviourAction for SeekBehaviour {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        match self.target.locate(ctx) {
+            Some((target, _)) => ctx.head_for(&target),
+            None => Steering::default(),
+        }
+    }
+}
+
+/// The player runs straight away from its target while it is closer than `panic_distance`.
+#[derive(Clone, Debug)]
+pub struct FleeBehaviour {
+    pub target: SteeringTarget,
+    pub panic_distance: f32,
+}
+
+impl Default for FleeBehaviour {
+    fn default() -> Self {
+        Self {
+            target: SteeringTarget::It,
+            panic_distance: 150.0,
+        }
+    }
+}
+
+impl BehaviourAction for FleeBehaviour {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let my_pos = ctx.current_player.0;
+        match self.target.locate(ctx) {
+            Some((target, _)) => flee(ctx, my_pos, &target, self.panic_distance),
+            None => Steering::default(),
+        }
+    }
+}
+
+/// Away from `threat`, if it is closer than `panic_distance`
+fn flee(
+    ctx: &BehaviourContext,
+    my_pos: &Position,
+    threat: &Position,
+    panic_distance: f32,
+) -> Steering {
+    let distance = ctx.topology.distance(my_pos, threat);
+    if distance > 0.0 && distance < panic_distance {
+        Steering::towards(ctx.topology.direction(threat, my_pos))
+    } else {
+        Steering::default()
+    }
+}
+
+/// The player heads for where its target will be when it gets there, judging by the
+/// target's current velocity.
+#[derive(Clone, Debug)]
+pub struct PursueBehaviour {
+    pub target: SteeringTarget,
+    /// How many ticks ahead the target's position is predicted at most
+    pub max_prediction: f32,
+}
+
+impl Default for PursueBehaviour {
+    fn default() -> Self {
+        Self {
+            target: SteeringTarget::Prey,
+            max_prediction: 30.0,
+        }
+    }
+}
+
+impl BehaviourAction for PursueBehaviour {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let (my_pos, my_vel) = ctx.current_player;
+        let (target, target_vel) = match self.target.locate(ctx) {
+            Some(target) => target,
+            None => return Steering::default(),
+        };
+
+        let distance = ctx.topology.distance(my_pos, &target);
+        let predicted = predict_position(
+            &target,
+            &target_vel,
+            distance,
+            my_vel.magnitude(),
+            self.max_prediction,
+        );
+        ctx.head_for(&predicted)
+    }
+}
+
+/// The player runs away from where its target will be when it could reach the player,
+/// while the target is closer than `panic_distance`.
+#[derive(Clone, Debug)]
+pub struct EvadeBehaviour {
+    pub target: SteeringTarget,
+    /// How many ticks ahead the target's position is predicted at most
+    pub max_prediction: f32,
+    pub panic_distance: f32,
+}
+
+impl Default for EvadeBehaviour {
+    fn default() -> Self {
+        Self {
+            target: SteeringTarget::It,
+            max_prediction: 30.0,
+            panic_distance: 150.0,
+        }
+    }
+}
+
+impl BehaviourAction for EvadeBehaviour {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let my_pos = ctx.current_player.0;
+        let (threat, threat_vel) = match self.target.locate(ctx) {
+            Some(threat) => threat,
+            None => return Steering::default(),
+        };
+
+        let distance = ctx.topology.distance(my_pos, &threat);
+        if distance >= self.panic_distance {
+            return Steering::default();
+        }
+        let predicted = predict_position(
+            &threat,
+            &threat_vel,
+            distance,
+            threat_vel.magnitude(),
+            self.max_prediction,
+        );
+        // A threat predicted right on the player is fled from where it is now
+        let steering = flee(ctx, my_pos, &predicted, f32::INFINITY);
+        if steering.is_idle() {
+            flee(ctx, my_pos, &threat, f32::INFINITY)
+        } else {
+            steering
+        }
+    }
+}
+
+/// The player heads for its target at full speed, slowing down to stop on it.
+#[derive(Clone, Debug)]
+pub struct ArriveBehaviour {
+    pub target: SteeringTarget,
+    pub max_speed: f32,
+    /// Distance to the target from which the player slows down
+    pub slowing_radius: f32,
+}
+
+impl Default for ArriveBehaviour {
+    fn default() -> Self {
+        Self {
+            target: SteeringTarget::Base,
+            max_speed: 2.0,
+            slowing_radius: 50.0,
+        }
+    }
+}
+
+impl BehaviourAction for ArriveBehaviour {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let my_pos = ctx.current_player.0;
+        let target = match self.target.locate(ctx) {
+            Some((target, _)) => target,
+            None => return Steering::default(),
+        };
+
+        let distance = ctx.topology.distance(my_pos, &target);
+        let speed = arrival_speed(distance, self.max_speed, self.slowing_radius);
+        ctx.head_for(&target).with_speed(speed)
+    }
+}
+
+/// The player roams around smoothly. Every tick it steers towards a point on a circle
+/// `distance` ahead of it. The point wanders around the circle, moving at most `jitter`
+/// radians a tick, and is kept in the player's [super::Blackboard] under [WANDER_ANGLE].
+#[derive(Clone, Debug)]
+pub struct WanderBehaviour {
+    pub distance: f32,
+    pub radius: f32,
+    pub jitter: f32,
+}
+
+/// Blackboard key of where the wander point is on its circle, in radians from straight ahead
+pub const WANDER_ANGLE: &str = "wander_angle";
+
+impl Default for WanderBehaviour {
+    fn default() -> Self {
+        Self {
+            distance: 40.0,
+            radius: 20.0,
+            jitter: 0.5,
+        }
+    }
+}
+
+impl BehaviourAction for WanderBehaviour {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        use std::f32::consts::{PI, TAU};
+
+        let rng = &mut ctx.rng.0;
+        let vel = ctx.current_player.1;
+        // Standing still, the player sets off any way
+        let heading = if vel.magnitude() > 0.0 {
+            vel.normalize()
+        } else {
+            Velocity::new(1.0, 0.0).rotate_angle(rng.gen_range(0.0..TAU))
+        };
+
+        let angle = ctx.blackboard.get(WANDER_ANGLE) + rng.gen_range(-self.jitter..=self.jitter);
+        let angle = (angle + PI).rem_euclid(TAU) - PI;
+        ctx.blackboard.set(WANDER_ANGLE, angle);
+
+        let center = heading.clone() * self.distance;
+        let offset = heading.rotate_angle(angle) * self.radius;
+        Steering::towards(Velocity::new(
+            center.0[0] + offset.0[0],
+            center.0[1] + offset.0[1],
+        ))
+    }
+}
+
+/// The player keeps clear of the neighbours closer than `radius`, the closer the neighbour
+/// the harder it pushes away. Obstacles are not taken into account.
+#[derive(Clone, Debug)]
+pub struct SeparationBehaviour {
+    pub radius: f32,
+}
+
+impl Default for SeparationBehaviour {
+    fn default() -> Self {
+        Self { radius: 30.0 }
+    }
+}
+
+impl BehaviourAction for SeparationBehaviour {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let my_pos = ctx.current_player.0;
+        let mut push = [0.0, 0.0];
+
+        for neighbour in ctx.nearest_5_neighbors.iter() {
+            let [dx, dy] = ctx.topology.offset(&my_pos.0, &neighbour.position.0);
+            let distance_squared = dx * dx + dy * dy;
+            if distance_squared > 0.0 && distance_squared < self.radius * self.radius {
+                // Away from the neighbour, by the inverse of the distance
+                push[0] -= dx / distance_squared;
+                push[1] -= dy / distance_squared;
+            }
+        }
+
+        if push == [0.0, 0.0] {
+            Steering::default()
+        } else {
+            Steering::towards(Velocity::new(push[0], push[1]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use shipyard::World;
+    use spade::rtree::RTree;
+
+    use super::{
+        arrival_speed, predict_position, EvadeBehaviour, PursueBehaviour, SeparationBehaviour,
+        WanderBehaviour, WANDER_ANGLE,
+    };
+    use crate::arena::{BoundaryPolicy, TerrainLayer};
+    use crate::behaviours::{
+        BehaviourAction, BehaviourContext, Blackboard, FlockWeights, Objectives, Steering,
+    };
+    use crate::entities_components::{
+        Endurance, PlayerRng, Position, RTreeData, TagState, Tagged, Velocity,
+    };
+    use crate::navigation::{NavGrid, NavPath, Navigator};
+    use crate::spatial::{ObstacleQuery, SpatialQuery, Topology};
+    use crate::SimConfig;
+
+    /// Steering of `behaviour` for a player at `me`, among `others` in an empty arena
+    fn steer(
+        behaviour: &impl BehaviourAction,
+        me: (Position, Velocity),
+        others: &[(Position, Velocity, TagState)],
+        blackboard: &mut Blackboard,
+    ) -> Steering {
+        let config = SimConfig::default();
+        let topology = Topology::for_policy(config.width, config.height, BoundaryPolicy::Bounce);
+        let mut world = World::default();
+        let entity_id = world.add_entity((me.0.clone(),));
+
+        let mut neighbours: Vec<RTreeData> = others
+            .iter()
+            .map(|(pos, vel, tag)| RTreeData {
+                entity_id: world.add_entity((pos.clone(),)),
+                position: pos.clone(),
+                velocity: vel.clone(),
+                recently_tagged: false,
+                tagged: Tagged(tag.clone()),
+                team: None,
+            })
+            .collect();
+        neighbours.sort_by(|a, b| {
+            let a = topology.distance(&me.0, &a.position);
+            let b = topology.distance(&me.0, &b.position);
+            a.partial_cmp(&b).unwrap()
+        });
+        let players = RTree::bulk_load(neighbours.clone());
+        let obstacles = RTree::new();
+        let grid = NavGrid::default();
+        let mut path = NavPath::default();
+        let terrain = TerrainLayer::default();
+        let endurance = Endurance(100, 100);
+        let flock_weights = FlockWeights::default();
+        let mut rng = PlayerRng::new(1, 1);
+
+        let mut ctx = BehaviourContext {
+            entity_id,
+            current_player: (&me.0, &me.1),
+            distance_to_it: 0.0,
+            nearest_5_neighbors: neighbours.iter().take(5).collect(),
+            players: SpatialQuery::new(&players, topology),
+            boundary: BoundaryPolicy::Bounce,
+            topology,
+            rng: &mut rng,
+            team: None,
+            objectives: Objectives::default(),
+            obstacles: ObstacleQuery::new(&obstacles),
+            navigation: Navigator {
+                grid: &grid,
+                path: &mut path,
+                repath_distance: config.nav_repath_distance,
+            },
+            terrain: &terrain,
+            endurance: &endurance,
+            bases: &[],
+            play_area: config.arena(),
+            flock_weights: &flock_weights,
+            blackboard,
+        };
+        behaviour.steer(&mut ctx)
+    }
+
+    #[test]
+    fn test_pursuit_prediction_and_arrival() {
+        let target = Position::new(100.0, 0.0);
+        let moving_up = Velocity::new(0.0, 1.0);
+
+        // 100 away at speed 2, the target is met 50 ticks ahead
+        let predicted = predict_position(&target, &moving_up, 100.0, 2.0, 60.0);
+        assert_eq!(predicted, Position::new(100.0, 50.0));
+        // But not predicted further ahead than allowed
+        let predicted = predict_position(&target, &moving_up, 100.0, 2.0, 10.0);
+        assert_eq!(predicted, Position::new(100.0, 10.0));
+
+        assert_eq!(arrival_speed(200.0, 2.0, 50.0), 2.0);
+        assert_eq!(arrival_speed(25.0, 2.0, 50.0), 1.0);
+        assert_eq!(arrival_speed(0.0, 2.0, 50.0), 0.0);
+    }
+
+    #[test]
+    fn test_pursue_and_evade() {
+        let me = (Position::new(100.0, 100.0), Velocity::new(2.0, 0.0));
+        let mut blackboard = Blackboard::default();
+
+        // Prey 100 away going up is met 30 ticks ahead at most, at (200, 130)
+        let prey = (
+            Position::new(200.0, 100.0),
+            Velocity::new(0.0, 1.0),
+            TagState::NotIt,
+        );
+        let direction = steer(
+            &PursueBehaviour::default(),
+            me.clone(),
+            &[prey],
+            &mut blackboard,
+        )
+        .direction
+        .unwrap();
+        assert!((direction.0[1] / direction.0[0] - 0.3).abs() < 1e-4);
+
+        // "It" 50 away going up at speed 2 is fled from where it is in 25 ticks, at (150, 150)
+        let it = (
+            Position::new(150.0, 100.0),
+            Velocity::new(0.0, 2.0),
+            TagState::It,
+        );
+        let direction = steer(
+            &EvadeBehaviour::default(),
+            me.clone(),
+            &[it],
+            &mut blackboard,
+        )
+        .direction
+        .unwrap();
+        assert!(direction.0[0] < 0.0 && (direction.0[0] - direction.0[1]).abs() < 1e-4);
+
+        // But not when it is too far away to worry about
+        let far_it = (
+            Position::new(400.0, 100.0),
+            Velocity::new(0.0, 2.0),
+            TagState::It,
+        );
+        assert!(steer(&EvadeBehaviour::default(), me, &[far_it], &mut blackboard).is_idle());
+    }
+
+    #[test]
+    fn test_separation() {
+        let me = (Position::new(100.0, 100.0), Velocity::new(2.0, 0.0));
+        let mut blackboard = Blackboard::default();
+        let still = Velocity::new(0.0, 0.0);
+        let at = |x: f32, y: f32| (Position::new(x, y), still.clone(), TagState::NotIt);
+
+        // Nobody within the radius
+        let steering = steer(
+            &SeparationBehaviour::default(),
+            me.clone(),
+            &[at(100.0, 150.0)],
+            &mut blackboard,
+        );
+        assert!(steering.is_idle());
+
+        // The closer neighbour on the right pushes harder than the one on the left
+        let direction = steer(
+            &SeparationBehaviour::default(),
+            me,
+            &[at(110.0, 100.0), at(80.0, 100.0)],
+            &mut blackboard,
+        )
+        .direction
+        .unwrap();
+        assert!(direction.0[0] < 0.0 && direction.0[1].abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_wander_angle_kept() {
+        let me = (Position::new(100.0, 100.0), Velocity::new(2.0, 0.0));
+        let wander = WanderBehaviour::default();
+        let mut blackboard = Blackboard::default();
+
+        // The wander point moves on from where it was, at most the jitter every tick
+        let mut angle = 0.0;
+        for _ in 0..5 {
+            steer(&wander, me.clone(), &[], &mut blackboard);
+            let next = blackboard.get(WANDER_ANGLE);
+            assert!((next - angle).abs() <= wander.jitter + 1e-6);
+            angle = next;
+        }
+        assert_ne!(angle, 0.0);
+    }
+}
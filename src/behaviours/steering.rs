@@ -0,0 +1,161 @@
+use shipyard::Component;
+
+use crate::entities_components::Velocity;
+
+use super::{BehaviourAction, BehaviourContext, SharedBehaviour};
+
+/// How a behaviour wants the player to move, kept for the player until the next tick.
+/// Turned into the player's [Velocity] by the `integrate_steering` system.
+#[derive(Clone, Default, Debug, PartialEq, Component)]
+pub struct Steering {
+    /// Heading the player wants to move in, the current heading is kept if `None`
+    pub direction: Option<Velocity>,
+    /// Speed the player wants to move at, the current speed is kept if `None`
+    pub speed: Option<f32>,
+    /// Turn in radians on top of the heading
+    pub turn: f32,
+}
+
+impl Steering {
+    pub fn towards(direction: Velocity) -> Self {
+        Self {
+            direction: Some(direction),
+            ..Self::default()
+        }
+    }
+
+    pub fn turn(angle: f32) -> Self {
+        Self {
+            turn: angle,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_speed(mut self, speed: f32) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+
+    /// Whether the player is asked to keep going as it is
+    pub fn is_idle(&self) -> bool {
+        self.direction.is_none() && self.speed.is_none() && self.turn == 0.0
+    }
+
+    /// Steerings averaged by their weights. Headings and speeds are only
+    /// averaged over the steerings asking for them.
+    pub fn blend(steerings: &[(f32, Steering)]) -> Self {
+        let mut direction = [0.0, 0.0];
+        let mut has_direction = false;
+        let (mut speed, mut speed_weight) = (0.0, 0.0);
+        let (mut turn, mut total_weight) = (0.0, 0.0);
+
+        for (weight, steering) in steerings {
+            if let Some(heading) = &steering.direction {
+                if heading.magnitude() > 0.0 {
+                    let heading = heading.normalize();
+                    direction[0] += heading.0[0] * weight;
+                    direction[1] += heading.0[1] * weight;
+                    has_direction = true;
+                }
+            }
+            if let Some(steering_speed) = steering.speed {
+                speed += steering_speed * weight;
+                speed_weight += weight;
+            }
+            turn += steering.turn * weight;
+            total_weight += weight;
+        }
+
+        Self {
+            direction: Some(Velocity::new(direction[0], direction[1]))
+                .filter(|heading| has_direction && heading.magnitude() > 0.0),
+            speed: Some(speed / speed_weight).filter(|_| speed_weight > 0.0),
+            turn: if total_weight > 0.0 {
+                turn / total_weight
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Several behaviours at once, their steerings blended by weight
+pub struct Blend {
+    pub behaviours: Vec<(f32, SharedBehaviour)>,
+}
+
+impl Blend {
+    pub fn new(behaviours: Vec<(f32, SharedBehaviour)>) -> Self {
+        Self { behaviours }
+    }
+}
+
+impl BehaviourAction for Blend {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        let steerings: Vec<(f32, Steering)> = self
+            .behaviours
+            .iter()
+            .map(|(weight, behaviour)| (*weight, behaviour.steer(ctx)))
+            .collect();
+        Steering::blend(&steerings)
+    }
+}
+
+/// Behaviours in order of priority, the first one asking for anything is followed.
+/// For example fleeing first, and wandering when there is nothing to flee from.
+pub struct Priority {
+    pub behaviours: Vec<SharedBehaviour>,
+}
+
+impl Priority {
+    pub fn new(behaviours: Vec<SharedBehaviour>) -> Self {
+        Self { behaviours }
+    }
+}
+
+impl BehaviourAction for Priority {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        self.behaviours
+            .iter()
+            .map(|behaviour| behaviour.steer(ctx))
+            .find(|steering| !steering.is_idle())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Steering;
+    use crate::entities_components::Velocity;
+
+    #[test]
+    fn test_blend_steerings() {
+        let blended = Steering::blend(&[
+            (
+                1.0,
+                Steering::towards(Velocity::new(1.0, 0.0)).with_speed(2.0),
+            ),
+            (1.0, Steering::towards(Velocity::new(0.0, 1.0))),
+            (2.0, Steering::turn(0.4)),
+        ]);
+
+        let direction = blended.direction.unwrap();
+        assert!((direction.0[0] - direction.0[1]).abs() < 1e-6);
+        // Only the first steering asks for a speed
+        assert_eq!(blended.speed, Some(2.0));
+        assert!((blended.turn - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_blend_weights_ignore_lengths() {
+        // A long heading counts as much as a short one of the same weight
+        let blended = Steering::blend(&[
+            (1.0, Steering::towards(Velocity::new(10.0, 0.0))),
+            (1.0, Steering::towards(Velocity::new(0.0, 1.0))),
+        ]);
+
+        let direction = blended.direction.unwrap();
+        let angle = direction.0[1].atan2(direction.0[0]);
+        assert!((angle - std::f32::consts::FRAC_PI_4).abs() < 1e-4);
+    }
+}
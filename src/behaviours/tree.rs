@@ -0,0 +1,574 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::path::Path;
+
+use shipyard::Component;
+
+use crate::entities_components::{Endurance, TagState};
+
+use super::{BehaviourAction, BehaviourContext, BehaviourRegistry, SharedBehaviour, Steering};
+
+/// What a player running a behaviour tree remembers between ticks.
+/// Trees write named values with `set` and read them with `check`,
+/// `hold` decorators keep their timers here.
+#[derive(Clone, Default, Debug, PartialEq, Component)]
+pub struct Blackboard {
+    pub values: HashMap<String, f32>,
+    /// Ticks left for each `hold` decorator, by the tree and the id of the decorator
+    pub timers: HashMap<(u64, usize), u32>,
+}
+
+impl Blackboard {
+    /// A value which was never set reads as zero
+    pub fn get(&self, key: &str) -> f32 {
+        self.values.get(key).copied().unwrap_or(0.0)
+    }
+
+    pub fn set(&mut self, key: impl Into<String>, value: f32) {
+        self.values.insert(key.into(), value);
+    }
+}
+
+/// Whether a node of a behaviour tree did what it is there for
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Status {
+    Success,
+    Failure,
+}
+
+/// Questions about the player and its surroundings
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    /// Endurance below this share of the max endurance
+    Tired(f32),
+    /// An "it" player within this distance
+    ItNear(f32),
+    /// A player "it" could tag within this distance
+    PreyNear(f32),
+    InBase,
+    CarryingFlag,
+    /// The blackboard value is above the given one
+    Above(String, f32),
+    /// The blackboard value is below the given one
+    Below(String, f32),
+}
+
+impl Condition {
+    fn holds(&self, ctx: &BehaviourContext) -> bool {
+        let my_pos = ctx.current_player.0;
+        let near = |tag: TagState, distance: f32| {
+            ctx.nearest_5_neighbors
+                .iter()
+                .any(|n| n.tagged.0 == tag && ctx.topology.distance(my_pos, &n.position) < distance)
+        };
+
+        match self {
+            Condition::Tired(share) => {
+                let Endurance(current, max) = *ctx.endurance;
+                (current as f32) < max as f32 * share
+            }
+            Condition::ItNear(distance) => near(TagState::It, *distance),
+            Condition::PreyNear(distance) => near(TagState::NotIt, *distance),
+            Condition::InBase => ctx.bases.iter().any(|base| base.contains(&my_pos.0)),
+            Condition::CarryingFlag => ctx.objectives.carrying_flag,
+            Condition::Above(key, value) => ctx.blackboard.get(key) > *value,
+            Condition::Below(key, value) => ctx.blackboard.get(key) < *value,
+        }
+    }
+}
+
+/// Nodes changing what their only child does
+#[derive(Clone, Debug, PartialEq)]
+pub enum Decorator {
+    /// Success becomes failure, and failure success
+    Invert,
+    /// Always a success, whatever the child does
+    Succeed,
+    /// Once the child succeeds, the node keeps succeeding for this many ticks.
+    /// The child is still run for its steering. Timers are kept by the `tree` the node is in,
+    /// a fingerprint of its text, and the `id` of the node within the tree.
+    Hold { ticks: u32, tree: u64, id: usize },
+}
+
+/// A node of a [BehaviourTree]
+pub enum Node {
+    /// Runs its children in order until one fails
+    Sequence(Vec<Node>),
+    /// Runs its children in order until one succeeds
+    Selector(Vec<Node>),
+    Decorator(Decorator, Box<Node>),
+    Condition(Condition),
+    /// A behaviour of the registry, it succeeds when it asks for anything
+    Action(String, SharedBehaviour),
+    /// Write a value to the blackboard, always a success
+    Set(String, f32),
+}
+
+impl Node {
+    /// Status of the node, and how it wants the player to move.
+    /// Of several children, the steering of the last succeeding one asking for anything is followed.
+    pub fn tick(&self, ctx: &mut BehaviourContext) -> (Status, Steering) {
+        match self {
+            Node::Sequence(children) => run_children(children, ctx, Status::Failure),
+            Node::Selector(children) => run_children(children, ctx, Status::Success),
+            Node::Decorator(decorator, child) => {
+                let (status, steering) = child.tick(ctx);
+                let status = match (decorator, status) {
+                    (Decorator::Invert, Status::Success) => Status::Failure,
+                    (Decorator::Invert, Status::Failure) => Status::Success,
+                    (Decorator::Succeed, _) => Status::Success,
+                    (Decorator::Hold { ticks, tree, id }, status) => {
+                        let timers = &mut ctx.blackboard.timers;
+                        if status == Status::Success {
+                            timers.insert((*tree, *id), *ticks);
+                            Status::Success
+                        } else {
+                            match timers.get_mut(&(*tree, *id)) {
+                                Some(left) if *left > 0 => {
+                                    *left -= 1;
+                                    Status::Success
+                                }
+                                _ => Status::Failure,
+                            }
+                        }
+                    }
+                };
+                (status, steering)
+            }
+            Node::Condition(condition) => {
+                let status = if condition.holds(ctx) {
+                    Status::Success
+                } else {
+                    Status::Failure
+                };
+                (status, Steering::default())
+            }
+            Node::Action(_, behaviour) => {
+                let steering = behaviour.steer(ctx);
+                let status = if steering.is_idle() {
+                    Status::Failure
+                } else {
+                    Status::Success
+                };
+                (status, steering)
+            }
+            Node::Set(key, value) => {
+                ctx.blackboard.set(key.clone(), *value);
+                (Status::Success, Steering::default())
+            }
+        }
+    }
+}
+
+/// Run `children` in order until one ends with `stop_at`
+fn run_children(
+    children: &[Node],
+    ctx: &mut BehaviourContext,
+    stop_at: Status,
+) -> (Status, Steering) {
+    let mut steering = Steering::default();
+    for child in children {
+        let (status, child_steering) = child.tick(ctx);
+        if status == Status::Success && !child_steering.is_idle() {
+            steering = child_steering;
+        }
+        if status == stop_at {
+            return (status, steering);
+        }
+    }
+
+    // Every child went the other way
+    let status = match stop_at {
+        Status::Success => Status::Failure,
+        Status::Failure => Status::Success,
+    };
+    (status, steering)
+}
+
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Sequence(children) => f.debug_tuple("Sequence").field(children).finish(),
+            Node::Selector(children) => f.debug_tuple("Selector").field(children).finish(),
+            Node::Decorator(decorator, child) => f
+                .debug_tuple("Decorator")
+                .field(decorator)
+                .field(child)
+                .finish(),
+            Node::Condition(condition) => f.debug_tuple("Condition").field(condition).finish(),
+            Node::Action(name, _) => f.debug_tuple("Action").field(name).finish(),
+            Node::Set(key, value) => f.debug_tuple("Set").field(key).field(value).finish(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TreeError {
+    Io(std::io::Error),
+    /// A line of the tree could not be understood
+    Malformed {
+        line: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for TreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TreeError::Io(err) => write!(f, "could not read behaviour tree: {}", err),
+            TreeError::Malformed { line, reason } => {
+                write!(f, "malformed behaviour tree, line {}: {}", line, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TreeError {}
+
+impl From<std::io::Error> for TreeError {
+    fn from(err: std::io::Error) -> Self {
+        TreeError::Io(err)
+    }
+}
+
+/// Decisions of a player as a tree of nodes, ticked from the root every tick.
+/// The player follows the steering of the tree, whether the root succeeds or not.
+///
+/// Trees are written one node per line, children indented under their parent:
+///
+/// ```text
+/// # Rest when tired, run from "it" when it comes close, wander otherwise
+/// selector
+///   sequence
+///     condition tired 0.3
+///     action seek_base
+///   sequence
+///     hold 60
+///       condition it_near 120
+///     action flee
+///   action wander
+/// ```
+///
+/// - `sequence` and `selector` take any number of children
+/// - `invert`, `succeed` and `hold TICKS` decorate a single child
+/// - `condition` is one of `tired SHARE`, `it_near DISTANCE`, `prey_near DISTANCE`,
+///   `in_base` or `carrying_flag`
+/// - `check KEY above VALUE` and `check KEY below VALUE` compare a value of the [Blackboard]
+/// - `action NAME` runs a behaviour of the [BehaviourRegistry]
+/// - `set KEY VALUE` writes a value to the [Blackboard]
+#[derive(Debug)]
+pub struct BehaviourTree {
+    pub root: Node,
+}
+
+/// A line of a tree file which is not empty or a comment
+struct Line<'t> {
+    number: usize,
+    indent: usize,
+    words: Vec<&'t str>,
+}
+
+impl BehaviourTree {
+    pub fn load(path: impl AsRef<Path>, registry: &BehaviourRegistry) -> Result<Self, TreeError> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        Self::from_text(&text, registry)
+    }
+
+    /// Parse a tree written as described on [BehaviourTree].
+    /// Actions are looked up in `registry`, so trees can run other trees registered before them.
+    pub fn from_text(text: &str, registry: &BehaviourRegistry) -> Result<Self, TreeError> {
+        let lines: Vec<Line> = text
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                let content = line.split('#').next().unwrap_or("").trim_end();
+                let words: Vec<&str> = content.split_whitespace().collect();
+                if words.is_empty() {
+                    return None;
+                }
+                Some(Line {
+                    number: i + 1,
+                    indent: content.len() - content.trim_start().len(),
+                    words,
+                })
+            })
+            .collect();
+
+        if lines.is_empty() {
+            return Err(TreeError::Malformed {
+                line: 1,
+                reason: "the tree has no nodes".to_string(),
+            });
+        }
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        let mut holds = HoldIds {
+            tree: hasher.finish(),
+            next: 0,
+        };
+        let mut next = 0;
+        let root = parse_node(&lines, &mut next, registry, &mut holds)?;
+        if let Some(line) = lines.get(next) {
+            return Err(malformed(line, "the tree has more than one root"));
+        }
+
+        Ok(Self { root })
+    }
+}
+
+impl BehaviourAction for BehaviourTree {
+    fn steer(&self, ctx: &mut BehaviourContext) -> Steering {
+        self.root.tick(ctx).1
+    }
+}
+
+fn malformed(line: &Line, reason: impl Into<String>) -> TreeError {
+    TreeError::Malformed {
+        line: line.number,
+        reason: reason.into(),
+    }
+}
+
+fn number<T: std::str::FromStr>(line: &Line, word: Option<&&str>) -> Result<T, TreeError> {
+    let word =
+        word.ok_or_else(|| malformed(line, format!("`{}` expects a number", line.words[0])))?;
+    word.parse()
+        .map_err(|_| malformed(line, format!("expected a number, got `{}`", word)))
+}
+
+/// Ids of the `hold` decorators of a tree, counted while it is parsed.
+/// Together with the fingerprint of the tree, trees running on the same [Blackboard]
+/// keep apart timers, and parsing a tree again gives the same ids.
+struct HoldIds {
+    tree: u64,
+    next: usize,
+}
+
+/// Parse the node on `lines[*next]` with all of its children, leaving `next` after them
+fn parse_node(
+    lines: &[Line],
+    next: &mut usize,
+    registry: &BehaviourRegistry,
+    holds: &mut HoldIds,
+) -> Result<Node, TreeError> {
+    let line = &lines[*next];
+    *next += 1;
+
+    // Children are the lines indented deeper than the node, all at the same indent
+    let mut children = Vec::new();
+    if let Some(child_indent) = lines
+        .get(*next)
+        .map(|child| child.indent)
+        .filter(|indent| *indent > line.indent)
+    {
+        while let Some(child) = lines.get(*next).filter(|child| child.indent > line.indent) {
+            if child.indent != child_indent {
+                return Err(malformed(child, "inconsistent indentation"));
+            }
+            children.push(parse_node(lines, next, registry, holds)?);
+        }
+    }
+
+    let words = &line.words;
+    let arity = |expected: usize, node: Node| {
+        if words.len() != expected {
+            Err(malformed(
+                line,
+                format!("unexpected arguments to `{}`", words[0]),
+            ))
+        } else {
+            Ok(node)
+        }
+    };
+    let node = match words[0] {
+        "sequence" | "selector" => {
+            if children.is_empty() {
+                return Err(malformed(line, format!("`{}` needs children", words[0])));
+            }
+            let node = if words[0] == "sequence" {
+                Node::Sequence(children)
+            } else {
+                Node::Selector(children)
+            };
+            return arity(1, node);
+        }
+        "invert" | "succeed" | "hold" => {
+            if children.len() != 1 {
+                return Err(malformed(
+                    line,
+                    format!("`{}` needs exactly one child", words[0]),
+                ));
+            }
+            let decorator = match words[0] {
+                "invert" => Decorator::Invert,
+                "succeed" => Decorator::Succeed,
+                _ => {
+                    let hold = Decorator::Hold {
+                        ticks: number(line, words.get(1))?,
+                        tree: holds.tree,
+                        id: holds.next,
+                    };
+                    holds.next += 1;
+                    hold
+                }
+            };
+            let expected = if words[0] == "hold" { 2 } else { 1 };
+            return arity(
+                expected,
+                Node::Decorator(decorator, Box::new(children.remove(0))),
+            );
+        }
+        "condition" => {
+            let condition = match words.get(1).copied() {
+                Some("tired") => Condition::Tired(number(line, words.get(2))?),
+                Some("it_near") => Condition::ItNear(number(line, words.get(2))?),
+                Some("prey_near") => Condition::PreyNear(number(line, words.get(2))?),
+                Some("in_base") => Condition::InBase,
+                Some("carrying_flag") => Condition::CarryingFlag,
+                _ => {
+                    return Err(malformed(
+                        line,
+                        "`condition` expects `tired`, `it_near`, `prey_near`, `in_base` \
+                         or `carrying_flag`",
+                    ))
+                }
+            };
+            let expected = match condition {
+                Condition::InBase | Condition::CarryingFlag => 2,
+                _ => 3,
+            };
+            arity(expected, Node::Condition(condition))?
+        }
+        "check" => {
+            let key = words
+                .get(1)
+                .ok_or_else(|| malformed(line, "`check` expects a key"))?
+                .to_string();
+            let value = number(line, words.get(3))?;
+            let condition = match words.get(2).copied() {
+                Some("above") => Condition::Above(key, value),
+                Some("below") => Condition::Below(key, value),
+                _ => return Err(malformed(line, "`check` expects `above` or `below`")),
+            };
+            arity(4, Node::Condition(condition))?
+        }
+        "action" => {
+            let name = words
+                .get(1)
+                .ok_or_else(|| malformed(line, "`action` expects a behaviour name"))?;
+            let behaviour = registry.get(name).ok_or_else(|| {
+                malformed(
+                    line,
+                    format!(
+                        "unknown behaviour `{}`, expected one of {}",
+                        name,
+                        registry.names().join(", ")
+                    ),
+                )
+            })?;
+            arity(2, Node::Action(name.to_string(), behaviour.clone()))?
+        }
+        "set" => {
+            let key = words
+                .get(1)
+                .ok_or_else(|| malformed(line, "`set` expects a key"))?
+                .to_string();
+            let value = number(line, words.get(2))?;
+            arity(3, Node::Set(key, value))?
+        }
+        other => return Err(malformed(line, format!("unknown node `{}`", other))),
+    };
+
+    // Conditions, actions and `set` are leaves
+    if !children.is_empty() {
+        return Err(malformed(
+            line,
+            format!("`{}` cannot have children", words[0]),
+        ));
+    }
+    Ok(node)
+}
+
+#[cfg(test)]
+mod test {
+    use shipyard::View;
+
+    use super::{BehaviourTree, Blackboard, Decorator, Node, TreeError};
+    use crate::behaviours::BehaviourRegistry;
+    use crate::{initialize_world_with_registry, SimConfig, TICK};
+
+    #[test]
+    fn test_behaviour_tree() {
+        let mut registry = BehaviourRegistry::default();
+
+        let unknown = BehaviourTree::from_text("selector\n  action teleport\n", &registry);
+        assert!(matches!(unknown, Err(TreeError::Malformed { line: 2, .. })));
+        let leaf_with_child = BehaviourTree::from_text("action flee\n  action wander\n", &registry);
+        assert!(matches!(
+            leaf_with_child,
+            Err(TreeError::Malformed { line: 1, .. })
+        ));
+
+        let tree = BehaviourTree::from_text(
+            "# Remember fleeing from \"it\"\n\
+             selector\n\
+             \x20 sequence\n\
+             \x20   hold 30\n\
+             \x20     condition it_near 100\n\
+             \x20   set scared 1\n\
+             \x20   action flee\n\
+             \x20 sequence\n\
+             \x20   set scared 0\n\
+             \x20   action wander\n",
+            &registry,
+        )
+        .unwrap();
+        registry.register("scared_of_it", tree);
+
+        let world = initialize_world_with_registry(
+            SimConfig {
+                player_count: 50,
+                not_it_behaviour: Some("scared_of_it".to_string()),
+                ..SimConfig::default()
+            },
+            registry,
+        );
+        world.run_workload(TICK).unwrap();
+
+        // Every "not it" player took one of the branches
+        let blackboards = world
+            .run(|v_blackboard: View<Blackboard>| {
+                v_blackboard.iter().cloned().collect::<Vec<Blackboard>>()
+            })
+            .unwrap();
+        assert_eq!(
+            blackboards
+                .iter()
+                .filter(|blackboard| blackboard.values.contains_key("scared"))
+                .count(),
+            49
+        );
+    }
+
+    #[test]
+    fn test_hold_timer_keys() {
+        let registry = BehaviourRegistry::default();
+        let hold_key = |text: &str| match BehaviourTree::from_text(text, &registry).unwrap().root {
+            Node::Decorator(Decorator::Hold { tree, id, .. }, _) => (tree, id),
+            _ => unreachable!(),
+        };
+        let it_near = "hold 30\n  condition it_near 100\n";
+        let prey_near = "hold 30\n  condition prey_near 100\n";
+
+        // The same tree parsed twice keeps its timers in the same place
+        assert_eq!(hold_key(it_near), hold_key(it_near));
+        assert_eq!(hold_key(it_near).1, 0);
+        // Holds of two trees, e.g. one for "it" and one for "not it", keep apart
+        assert_ne!(hold_key(it_near), hold_key(prey_near));
+    }
+}
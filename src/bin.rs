@@ -1,51 +1,341 @@
 use std::env;
+use std::fs::File;
+use std::path::Path;
+use std::process;
+#[cfg(feature = "render")]
 use std::rc::Rc;
-use std::time::SystemTime;
+use std::time::Duration;
 
-use shipyard::*;
+use tag::arena::{ArenaGenerator, MapLayout};
+use tag::behaviours::{BehaviourRegistry, BehaviourTree};
+#[cfg(feature = "render")]
+use tag::render::*;
+#[cfg(feature = "render")]
+use tag::{initialize_world_with_registry, step};
+use tag::{
+    run_headless_with_registry, GameMode, OutsidePenalty, ShrinkSchedule, SimConfig, WinCondition,
+};
 
-use tag::entities_components::*;
-use tag::systems::*;
-use tag::{initialize_world, HEIGHT, WIDTH};
+const USAGE: &str = "\
+Usage:
+    tagbin <players>
+    tagbin [run] [--headless] [--ticks N] [--players P] [--its I] [--mode M] [--seed S]
+                 [--recovery SECONDS] [--sir-csv PATH] [--despawn] [--rounds R]
+                 [--time-limit SECONDS] [--teams T] [--map PATH] [--cell-size SIZE]
+                 [--arena KIND] [--max-dwell SECONDS] [--shrink SECONDS] [--outside-tags]
+                 [--it-behaviour NAME] [--not-it-behaviour NAME] [--tree PATH]
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+`run` is optional when giving options, `tagbin --map arena.png` is the same as `tagbin run --map arena.png`.
 
-    // Number of players in `world`
-    let player_count = if let Some(Ok(player_count)) = args.get(1).map(|x| str::parse::<usize>(&x))
-    {
-        player_count
+Options:
+    --headless   Run without a window, print a summary when done (requires --ticks)
+    --ticks N    Number of ticks to simulate
+    --players P  Number of players in the world
+    --its I      Number of players which start as \"it\"
+    --mode M     Game mode, `classic`, `freeze`, `infection`, `elimination` or `ctf`
+    --seed S     Seed of the simulation, random if not given
+    --recovery SECONDS  In infection mode, time after which infected players recover
+    --sir-csv PATH      In infection mode, write the epidemic curve of a headless run to PATH
+    --despawn    In elimination mode, remove tagged players from the arena
+    --rounds R   Number of rounds in the match
+    --time-limit SECONDS  End every round after this much simulated time
+    --teams T    In capture the flag, number of teams (default 2)
+    --map PATH   Lay out the arena after a `.png` image or a text map
+    --cell-size SIZE  Size of a map pixel or character in the arena (default 16)
+    --arena KIND Generate the arena from the seed, `maze`, `cave` or `pillars`
+    --max-dwell SECONDS  Force players out of a safe zone after this much simulated time
+    --shrink SECONDS     Shrink the play area to a fifth of the arena over this much simulated time
+    --outside-tags       Tag players outside of the shrinking play area, instead of tiring them
+    --it-behaviour NAME      Behaviour of \"it\" players, e.g. `chase_nearest` or `random_it`
+    --not-it-behaviour NAME  Behaviour of other players, e.g. `oppose_it` or `seek_base`
+    --tree PATH  Load a behaviour tree, usable as a behaviour named after the file";
+
+/// Options given on the command line
+struct Args {
+    headless: bool,
+    ticks: Option<u64>,
+    config: SimConfig,
+    /// The built-in behaviours, and the behaviour trees loaded
+    registry: BehaviourRegistry,
+    sir_csv: Option<String>,
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} expects a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got `{}`", flag, value))
+}
+
+/// A number of seconds, which `Duration` can hold
+fn parse_seconds(flag: &str, value: Option<&String>) -> Result<Duration, String> {
+    let seconds: f64 = parse_number(flag, value)?;
+    if (0.0..u64::MAX as f64).contains(&seconds) {
+        Ok(Duration::from_secs_f64(seconds))
     } else {
-        panic!("First argument given is not a number. Please enter number of players.");
+        Err(format!(
+            "{} expects a number of seconds, got `{}`",
+            flag, seconds
+        ))
+    }
+}
+
+/// A behaviour name known to the `registry`
+fn check_behaviour(flag: &str, name: &str, registry: &BehaviourRegistry) -> Result<(), String> {
+    if registry.contains(name) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} expects one of {}, got `{}`",
+            flag,
+            registry.names().join(", "),
+            name
+        ))
+    }
+}
+
+/// Register the behaviour tree at `path` under the name of the file, without its extension
+fn load_tree(path: &str, registry: &mut BehaviourRegistry) -> Result<(), String> {
+    let name = Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| format!("{}: cannot name a behaviour after this path", path))?
+        .to_string();
+    let tree = BehaviourTree::load(path, registry).map_err(|err| format!("{}: {}", path, err))?;
+    registry.register(name, tree);
+    Ok(())
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    // A fresh seed for every run, unless one is given
+    let mut parsed = Args {
+        headless: false,
+        ticks: None,
+        sir_csv: None,
+        registry: BehaviourRegistry::default(),
+        config: SimConfig {
+            seed: rand::random(),
+            ..SimConfig::default()
+        },
     };
 
+    let mut recovery = None;
+    let mut despawn = false;
+    let mut team_count = 2;
+    let mut map_path = None;
+    let mut cell_size = 16.0;
+    let mut generator = None;
+    let mut outside_tags = false;
+    // `run` is the default subcommand when options are given right away
+    let run_args = match args.first().map(String::as_str) {
+        Some("run") => Some(&args[1..]),
+        Some(flag) if flag.starts_with("--") => Some(args),
+        _ => None,
+    };
+    match (run_args, args.first()) {
+        (Some(run_args), _) => {
+            let mut args = run_args.iter();
+            while let Some(flag) = args.next() {
+                match flag.as_str() {
+                    "--headless" => parsed.headless = true,
+                    "--ticks" => parsed.ticks = Some(parse_number(flag, args.next())?),
+                    "--players" => parsed.config.player_count = parse_number(flag, args.next())?,
+                    "--its" => parsed.config.it_count = parse_number(flag, args.next())?,
+                    "--mode" => {
+                        parsed.config.game_mode = match args.next().map(String::as_str) {
+                            Some("classic") => GameMode::Classic,
+                            Some("freeze") => GameMode::FreezeTag,
+                            Some("infection") => GameMode::Infection { recovery: None },
+                            Some("elimination") => GameMode::Elimination {
+                                despawn_tagged: false,
+                            },
+                            Some("ctf") => GameMode::CaptureTheFlag {
+                                teams: 2,
+                                captures_to_win: 3,
+                            },
+                            _ => {
+                                return Err("--mode expects `classic`, `freeze`, `infection`, \
+                                     `elimination` or `ctf`"
+                                    .to_string())
+                            }
+                        }
+                    }
+                    "--recovery" => {
+                        recovery = Some(parse_seconds(flag, args.next())?);
+                    }
+                    "--despawn" => despawn = true,
+                    "--teams" => team_count = parse_number(flag, args.next())?,
+                    "--map" => map_path = Some(args.next().ok_or("--map expects a path")?.clone()),
+                    "--cell-size" => cell_size = parse_number(flag, args.next())?,
+                    "--arena" => {
+                        generator = Some(
+                            args.next()
+                                .and_then(|name| ArenaGenerator::from_name(name))
+                                .ok_or("--arena expects `maze`, `cave` or `pillars`")?,
+                        )
+                    }
+                    "--shrink" => {
+                        parsed.config.shrink = Some(ShrinkSchedule {
+                            delay: Duration::from_secs(0),
+                            duration: parse_seconds(flag, args.next())?,
+                            final_scale: 0.2,
+                            penalty: OutsidePenalty::Endurance(5),
+                        });
+                    }
+                    "--outside-tags" => outside_tags = true,
+                    "--it-behaviour" => {
+                        let name = args.next().ok_or("--it-behaviour expects a name")?;
+                        parsed.config.it_behaviour = Some(name.clone());
+                    }
+                    "--not-it-behaviour" => {
+                        let name = args.next().ok_or("--not-it-behaviour expects a name")?;
+                        parsed.config.not_it_behaviour = Some(name.clone());
+                    }
+                    "--tree" => {
+                        let path = args.next().ok_or("--tree expects a path")?;
+                        load_tree(path, &mut parsed.registry)?;
+                    }
+                    "--max-dwell" => {
+                        parsed.config.max_base_dwell = Some(parse_seconds(flag, args.next())?);
+                    }
+                    "--rounds" => parsed.config.rounds = parse_number(flag, args.next())?,
+                    "--time-limit" => {
+                        let limit = parse_seconds(flag, args.next())?;
+                        parsed
+                            .config
+                            .win_conditions
+                            .push(WinCondition::TimeLimit(limit));
+                    }
+                    "--sir-csv" => {
+                        parsed.sir_csv =
+                            Some(args.next().ok_or("--sir-csv expects a path")?.clone())
+                    }
+                    "--seed" => parsed.config.seed = parse_number(flag, args.next())?,
+                    _ => return Err(format!("Unknown option `{}`", flag)),
+                }
+            }
+        }
+        // The original form, only taking the number of players
+        (None, Some(player_count)) if args.len() == 1 => {
+            parsed.config.player_count = player_count
+                .parse()
+                .map_err(|_| "First argument given is not a number".to_string())?;
+        }
+        _ => return Err("Please enter number of players".to_string()),
+    }
+
+    if let GameMode::Infection {
+        recovery: mode_recovery,
+    } = &mut parsed.config.game_mode
+    {
+        *mode_recovery = recovery;
+    }
+    if let GameMode::Elimination { despawn_tagged } = &mut parsed.config.game_mode {
+        *despawn_tagged = despawn;
+    }
+    if let GameMode::CaptureTheFlag { teams, .. } = &mut parsed.config.game_mode {
+        if team_count < 2 {
+            return Err("--teams expects at least 2 teams".to_string());
+        }
+        *teams = team_count;
+    }
+
+    if let (true, Some(schedule)) = (outside_tags, &mut parsed.config.shrink) {
+        schedule.penalty = OutsidePenalty::Tag;
+    }
+
+    if let Some(path) = map_path {
+        let map = MapLayout::load(&path, cell_size).map_err(|err| format!("{}: {}", path, err))?;
+        parsed.config = parsed.config.with_map(map);
+    } else if let Some(generator) = generator {
+        // Cells covering the arena, generated from the seed of the simulation
+        let cols = (parsed.config.width / cell_size).ceil() as usize;
+        let rows = (parsed.config.height / cell_size).ceil() as usize;
+        let map = generator.generate(
+            cols,
+            rows,
+            cell_size,
+            parsed.config.player_size,
+            parsed.config.seed,
+        );
+        parsed.config = parsed.config.with_map(map);
+    }
+
+    // Trees may be loaded after the behaviours naming them
+    if let Some(name) = &parsed.config.it_behaviour {
+        check_behaviour("--it-behaviour", name, &parsed.registry)?;
+    }
+    if let Some(name) = &parsed.config.not_it_behaviour {
+        check_behaviour("--not-it-behaviour", name, &parsed.registry)?;
+    }
+
+    if parsed.headless && parsed.ticks.is_none() {
+        return Err("A headless run needs --ticks".to_string());
+    }
+
+    Ok(parsed)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let args = parse_args(&args).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        process::exit(2);
+    });
+
+    if let (true, Some(ticks)) = (args.headless, args.ticks) {
+        let summary = run_headless_with_registry(args.config, args.registry, ticks);
+        print!("{}", summary);
+
+        if let Some(path) = args.sir_csv {
+            let written =
+                File::create(&path).and_then(|file| summary.epidemic_curve.write_csv(file));
+            if let Err(err) = written {
+                eprintln!("Could not write epidemic curve to {}: {}", path, err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    #[cfg(feature = "render")]
+    run_window(args.config, args.registry, args.ticks);
+
+    #[cfg(not(feature = "render"))]
+    {
+        eprintln!("tagbin was built without the `render` feature, use `run --headless`");
+        process::exit(2);
+    }
+}
+
+/// Run the simulation in a raylib window until it is closed, or `ticks` have passed.
+#[cfg(feature = "render")]
+fn run_window(config: SimConfig, registry: BehaviourRegistry, ticks: Option<u64>) {
     let (mut rl, thread) = raylib::init()
-        .size(WIDTH as i32, HEIGHT as i32)
+        .size(config.width as i32, config.height as i32)
         .title("Tag!")
         .build();
-    rl.set_target_fps(60);
+    // One tick per frame keeps simulated time in step with the wall-clock
+    rl.set_target_fps((1.0 / config.tick_duration.as_secs_f32()).round() as u32);
 
-    let world = initialize_world(player_count);
+    let world = initialize_world_with_registry(config, registry);
 
     world.add_unique(RLHandle(rl)).unwrap();
     world.add_unique_non_send_sync(RLThread(thread)).unwrap();
 
     let world = Rc::new(world);
 
+    let mut ticks_run = 0;
     loop {
         // Main loop is checking the window close state
-        if window_should_close(&world) {
+        if window_should_close(&world) || Some(ticks_run) == ticks {
             break;
         }
 
         // And advancing the ECS one "tick" and "frame" at a time.
-        world.run_workload(TICK).unwrap();
+        step(&world);
         world.run_workload(FRAME).unwrap();
-
-        // Change timestamp after every frame
-        world.borrow::<UniqueViewMut<Time>>().unwrap().0 = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .expect("time to be readable")
-            .as_millis();
+        ticks_run += 1;
     }
 }
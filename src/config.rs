@@ -0,0 +1,315 @@
+use std::ops::Range;
+use std::time::Duration;
+
+use rand::distributions::Distribution;
+use rand::Rng;
+use shipyard::Component;
+
+use crate::arena::{BoundaryRules, MapLayout, Shape, Terrain, Tile, Zone};
+use crate::behaviours::{BehaviourRegistry, FlockWeights, UnknownBehaviour};
+use crate::entities_components::{Endurance, GameMode, PlayerBehaviour, Position, TagState};
+use crate::game::WinCondition;
+use crate::spatial::Topology;
+
+/// Runtime parameters of a simulation.
+/// The config is handed to [crate::initialize_world] and stored in the `world` as a unique,
+/// such that every system reads the same values.
+#[derive(Clone, Debug, Component)]
+pub struct SimConfig {
+    /// Seed of every random decision in the simulation.
+    /// The same seed and config give identical runs.
+    pub seed: u64,
+    /// The rules of the game
+    pub game_mode: GameMode,
+    /// Conditions ending a round, on top of the ones of the game mode
+    pub win_conditions: Vec<WinCondition>,
+    /// Number of rounds in a match
+    pub rounds: u32,
+    /// Simulated time before the first round begins
+    pub lobby_duration: Duration,
+    /// Simulated time between the end of a round and the start of the next one
+    pub intermission: Duration,
+    /// Name of the behaviour of "it" players, instead of the default one of the game mode
+    pub it_behaviour: Option<String>,
+    /// Name of the behaviour of "not it" players, instead of the default one of the game mode
+    pub not_it_behaviour: Option<String>,
+    /// How players move in a flock, unless they are given their own [FlockWeights]
+    pub flocking: FlockWeights,
+    /// Number of players in the `world`
+    pub player_count: usize,
+    /// Number of players which are "it" when the game begins
+    pub it_count: usize,
+    /// Width of the arena
+    pub width: f32,
+    /// Height of the arena
+    pub height: f32,
+    /// How players are kept within the arena, depending on whether they are "it"
+    pub boundary_rules: BoundaryRules,
+    /// Static obstacles placed in the arena
+    pub obstacles: Vec<Shape>,
+    /// Areas players are placed in, anywhere in the arena if empty
+    pub spawn_zones: Vec<Zone>,
+    /// Bases where players cannot be tagged and do not tire, circles or boxes
+    pub safe_zones: Vec<Shape>,
+    /// Simulated time players may stay in a base before they are forced out, unlimited if `None`
+    pub max_base_dwell: Option<Duration>,
+    /// How the play area closes in during a round, the whole arena stays in play if `None`
+    pub shrink: Option<ShrinkSchedule>,
+    /// Areas of mud, ice and rest, later areas cover earlier ones
+    pub terrain: Vec<(Zone, Terrain)>,
+    /// The map the arena was built from, see [SimConfig::with_map]
+    pub map: Option<MapLayout>,
+    /// Size of the cells players find their way around obstacles with
+    pub nav_cell_size: f32,
+    /// How far the target of a path may move before the path is searched again
+    pub nav_repath_distance: f32,
+    /// Largest turn in radians a player makes in a tick, whatever its behaviour asks for
+    pub max_turn_rate: f32,
+    /// Largest change of speed of a player in a tick
+    pub max_acceleration: f32,
+    /// Radius of a player, players collide when within twice this distance
+    pub player_size: f32,
+    /// Which of the touching players an "it" player tags
+    pub target_selection: TargetSelection,
+    /// Simulated time that passes with every tick
+    pub tick_duration: Duration,
+    /// Simulated time before a tagged player can be tagged again
+    pub tag_cooldown: Duration,
+    /// Chance per tick that a "not it" player regains endurance
+    pub not_it_regen_chance: f64,
+    /// Chance per tick that an "it" player regains endurance
+    pub it_regen_chance: f64,
+    /// Amount of endurance regained when regeneration happens
+    pub regen_amount: Range<u16>,
+    /// Range of the initial (and max) endurance given to players
+    pub endurance_range: Range<u16>,
+}
+
+/// How an "it" player picks whom to tag, when touching several players who can be tagged
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TargetSelection {
+    Nearest,
+    Random,
+    /// The most tired player, the nearest of them on ties
+    LowestEndurance,
+}
+
+/// The play area closing in on the centre of the arena over a round, like in a battle royale.
+/// Players can still leave the area, but pay the [OutsidePenalty] every tick they are out.
+#[derive(Clone, Debug)]
+pub struct ShrinkSchedule {
+    /// Simulated time into the round before the area starts shrinking
+    pub delay: Duration,
+    /// Simulated time the area takes to shrink to its final size
+    pub duration: Duration,
+    /// Share of the width and height of the arena left in play in the end
+    pub final_scale: f32,
+    pub penalty: OutsidePenalty,
+}
+
+/// What happens to players outside of the play area
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutsidePenalty {
+    /// Endurance lost every tick
+    Endurance(u16),
+    /// Players are tagged as if by an "it" player
+    Tag,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            game_mode: GameMode::default(),
+            win_conditions: vec![],
+            rounds: 1,
+            lobby_duration: Duration::from_secs(0),
+            intermission: Duration::from_secs(2),
+            it_behaviour: None,
+            not_it_behaviour: None,
+            flocking: FlockWeights::default(),
+            player_count: 100,
+            it_count: 1,
+            width: 1024.0,
+            height: 1024.0,
+            boundary_rules: BoundaryRules::default(),
+            obstacles: vec![],
+            spawn_zones: vec![],
+            safe_zones: vec![],
+            max_base_dwell: None,
+            shrink: None,
+            terrain: vec![],
+            map: None,
+            nav_cell_size: 16.0,
+            nav_repath_distance: 32.0,
+            // Players turn as sharply as their behaviours ask
+            max_turn_rate: std::f32::consts::PI,
+            max_acceleration: 0.1,
+            player_size: 10.0,
+            target_selection: TargetSelection::Nearest,
+            // 60 ticks per simulated second
+            tick_duration: Duration::from_secs(1) / 60,
+            // 5 seconds after tagging, players can be tagged again
+            tag_cooldown: Duration::from_secs(5),
+            not_it_regen_chance: 0.01,
+            it_regen_chance: 0.05,
+            regen_amount: 0..50,
+            endurance_range: 600..800,
+        }
+    }
+}
+
+impl SimConfig {
+    /// Behaviours players start with, those of the game mode unless the config names others
+    pub fn player_behaviour(&self) -> PlayerBehaviour {
+        let mut behaviour = PlayerBehaviour::for_mode(&self.game_mode);
+        if let Some(name) = &self.it_behaviour {
+            behaviour.it_behaviour = name.clone();
+        }
+        if let Some(name) = &self.not_it_behaviour {
+            behaviour.not_it_behaviour = name.clone();
+        }
+        behaviour
+    }
+
+    /// Every behaviour players start with is in `registry`.
+    /// Players whose behaviour is missing keep their course, see [crate::UnresolvedBehaviours].
+    pub fn check_behaviours(&self, registry: &BehaviourRegistry) -> Result<(), UnknownBehaviour> {
+        let behaviour = self.player_behaviour();
+        for name in [&behaviour.it_behaviour, &behaviour.not_it_behaviour].iter() {
+            if !registry.contains(name) {
+                return Err(UnknownBehaviour(name.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Lay out the arena after `map`: its size, walls as obstacles, spawn areas, bases and terrain
+    pub fn with_map(mut self, map: MapLayout) -> Self {
+        self.width = map.width();
+        self.height = map.height();
+        self.obstacles.extend(map.walls());
+        self.spawn_zones = map.zones_of(Tile::Spawn);
+        self.safe_zones.extend(
+            map.zones_of(Tile::SafeZone)
+                .into_iter()
+                .map(|zone| Shape::Box {
+                    min: zone.min,
+                    max: zone.max,
+                }),
+        );
+        for terrain in [Terrain::Mud, Terrain::Ice, Terrain::Rest].iter() {
+            let zones = map.zones_where(|tile| Terrain::from_tile(tile) == Some(*terrain));
+            self.terrain
+                .extend(zones.into_iter().map(|zone| (zone, *terrain)));
+        }
+        self.map = Some(map);
+        self
+    }
+
+    /// The whole arena
+    pub fn arena(&self) -> Zone {
+        Zone::new([0.0, 0.0], [self.width, self.height])
+    }
+
+    /// The area in play `elapsed` into a round, following [SimConfig::shrink]
+    pub fn play_area_at(&self, elapsed: Duration) -> Zone {
+        let schedule = match &self.shrink {
+            Some(schedule) => schedule,
+            None => return self.arena(),
+        };
+        let shrinking = elapsed.checked_sub(schedule.delay).unwrap_or_default();
+        let progress = if schedule.duration.as_secs_f32() > 0.0 {
+            f32::min(
+                shrinking.as_secs_f32() / schedule.duration.as_secs_f32(),
+                1.0,
+            )
+        } else {
+            1.0
+        };
+        self.arena()
+            .scaled(1.0 - (1.0 - schedule.final_scale) * progress)
+    }
+
+    /// The arena as seen by a player in the given tag state
+    pub fn topology_for(&self, state: &TagState) -> Topology {
+        Topology::for_policy(
+            self.width,
+            self.height,
+            self.boundary_rules.for_state(state),
+        )
+    }
+
+    /// The arena in which players touch each other
+    pub fn topology(&self) -> Topology {
+        Topology::for_rules(self.width, self.height, &self.boundary_rules)
+    }
+
+    /// The base `pos` lies in, if any
+    pub fn base_at(&self, pos: &Position) -> Option<&Shape> {
+        self.safe_zones.iter().find(|base| base.contains(&pos.0))
+    }
+
+    /// Number of teams, `0` unless the game mode is played in teams
+    pub fn team_count(&self) -> usize {
+        match self.game_mode {
+            GameMode::CaptureTheFlag { teams, .. } => teams,
+            _ => 0,
+        }
+    }
+
+    /// The territory of `team`, the arena is split in vertical strips of equal width
+    pub fn home_zone(&self, team: usize) -> Zone {
+        let strip = self.width / self.team_count().max(1) as f32;
+        Zone::new(
+            [team as f32 * strip, 0.0],
+            [(team + 1) as f32 * strip, self.height],
+        )
+    }
+
+    /// The team whose territory `pos` lies in, if playing in teams
+    pub fn territory_of(&self, pos: &Position) -> Option<usize> {
+        (0..self.team_count()).find(|team| self.home_zone(*team).contains(pos))
+    }
+
+    /// Where the flag of `team` stands until captured
+    pub fn flag_home(&self, team: usize) -> Position {
+        self.home_zone(team).center()
+    }
+
+    /// Where players jailed by `team` are held, at the far end of its territory from the flag
+    pub fn jail(&self, team: usize) -> Position {
+        let zone = self.home_zone(team);
+        Position::new(zone.center().0[0], zone.max[1] - self.player_size * 3.0)
+    }
+}
+
+/// Players are placed uniformly within the spawn zones, or within the arena if there are none
+impl Distribution<Position> for SimConfig {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Position {
+        let area = |zone: &Zone| (zone.max[0] - zone.min[0]) * (zone.max[1] - zone.min[1]);
+        let total_area: f32 = self.spawn_zones.iter().map(area).sum();
+        if total_area <= 0.0 {
+            return self.arena().sample(rng);
+        }
+
+        // Larger zones are picked more often, so that players are spread evenly
+        let mut pick = rng.gen_range(0.0..total_area);
+        let zone = self
+            .spawn_zones
+            .iter()
+            .find(|zone| {
+                pick -= area(zone);
+                pick < 0.0
+            })
+            .unwrap_or(&self.spawn_zones[self.spawn_zones.len() - 1]);
+        zone.sample(rng)
+    }
+}
+
+impl Distribution<Endurance> for SimConfig {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Endurance {
+        let initial = rng.gen_range(self.endurance_range.clone());
+        Endurance(initial, initial)
+    }
+}
@@ -1,11 +1,12 @@
+use std::time::Duration;
+
 use rand::distributions::{Distribution, Standard};
-use rand::Rng;
-use raylib::math::Vector2;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use shipyard::{Component, EntityId};
 use spade::rtree::RTree;
 
-use crate::behaviours::{ItBehaviour, NotItBehaviour};
-use crate::{HEIGHT, WIDTH};
+use crate::arena::{Shape, Zone};
 
 /// A geometric object that has `x` and `y` components
 
@@ -16,6 +17,15 @@ pub type Geo2D = [f32; 2];
 pub enum TagState {
     NotIt,
     It,
+    /// Tagged in freeze tag, the player cannot move until it is freed
+    Frozen,
+    /// Recovered from being "it" in infection tag, the player cannot be tagged anymore
+    Recovered,
+    /// Tagged in elimination, the player is out for the rest of the round
+    Eliminated,
+    /// Tagged in enemy territory in capture the flag, the player waits in jail
+    /// until a teammate frees it
+    Jailed,
 }
 
 impl Default for TagState {
@@ -24,29 +34,40 @@ impl Default for TagState {
     }
 }
 
+/// The rules of the game being played
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum GameMode {
+    /// A tagged player becomes "it", and the "it" player who tagged them is freed
+    Classic,
+    /// Tagged players freeze in place until a free player touches them.
+    /// "It" players stay "it", and the round is over once everyone else is frozen.
+    FreezeTag,
+    /// Tagged players are infected, become "it" and spread it further.
+    /// With a `recovery` time, infected players recover after having been "it" that long.
+    /// The round is over once nobody is infected, or nobody is left to infect.
+    Infection { recovery: Option<Duration> },
+    /// Tagged players are out for the rest of the round, and removed from the `world`
+    /// if `despawn_tagged` is set. The last untagged player wins.
+    Elimination { despawn_tagged: bool },
+    /// Players split into `teams`, each defending a home territory and its flag.
+    /// "It" players guard their flag, the others go for the flags of the other teams.
+    /// Players tagged in enemy territory are jailed, and the first team to bring
+    /// `captures_to_win` flags home wins.
+    CaptureTheFlag { teams: usize, captures_to_win: u32 },
+}
+
+impl Default for GameMode {
+    fn default() -> Self {
+        Self::Classic
+    }
+}
+
 // Components
 
 /// Position is a conventional variant of a point, it denotes a location in space
 #[derive(PartialEq, Clone, Debug, Component)]
 pub struct Position(pub Geo2D);
 
-impl Distribution<Position> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Position {
-        Position::new(
-            rng.gen_range(0.0..(WIDTH as f32)),
-            rng.gen_range(0.0..(HEIGHT as f32)),
-        )
-    }
-}
-impl Into<Vector2> for &Position {
-    fn into(self) -> Vector2 {
-        Vector2 {
-            x: self.0[0],
-            y: self.0[1],
-        }
-    }
-}
-
 impl Position {
     pub fn new(x: f32, y: f32) -> Self {
         Self([x, y])
@@ -97,21 +118,15 @@ impl Velocity {
         f32::sqrt(fx * fx + fy * fy)
     }
 
-    /// Quake-style fast inverse square root use for normalizing vectors
-    /// https://stackoverflow.com/a/59083859
-    fn inv_sqrt(&self) -> f32 {
-        let x = self.magnitude();
-        let i = x.to_bits();
-        let i = 0x5f3759df - (i >> 1);
-        let y = f32::from_bits(i);
-
-        y * (1.5 - 0.5 * x * y * y)
-    }
-
-    /// Normalize given vector, get new vector from that.
+    /// Normalize given vector, get new vector of length 1 from that.
+    /// A zero vector stays zero, it has no direction to keep.
     pub fn normalize(&self) -> Self {
-        let factor = self.inv_sqrt();
-        self.clone() * factor
+        let magnitude = self.magnitude();
+        if magnitude > 0.0 {
+            self.clone() / magnitude
+        } else {
+            self.clone()
+        }
     }
 
     pub fn angle_between(&self, other: &Velocity) -> f32 {
@@ -173,34 +188,181 @@ impl std::ops::Sub<Velocity> for Velocity {
 #[derive(Clone, Default, Debug, Component)]
 pub struct Tagged(pub TagState);
 
-/// How does the player behave
-
-#[derive(Default, Debug, Component)]
+/// How does the player behave, by the names of its behaviours in the
+/// [crate::behaviours::BehaviourRegistry]
+#[derive(Clone, Debug, Component)]
 pub struct PlayerBehaviour {
-    pub it_behaviour: ItBehaviour,
-    pub not_it_behaviour: NotItBehaviour,
+    pub it_behaviour: String,
+    pub not_it_behaviour: String,
+}
+
+impl Default for PlayerBehaviour {
+    fn default() -> Self {
+        Self::new("chase_nearest", "oppose_it")
+    }
 }
 
-/// Epoch timestamp in milliseconds
+impl PlayerBehaviour {
+    pub fn new(it_behaviour: impl Into<String>, not_it_behaviour: impl Into<String>) -> Self {
+        Self {
+            it_behaviour: it_behaviour.into(),
+            not_it_behaviour: not_it_behaviour.into(),
+        }
+    }
+
+    /// Default behaviours suited for the rules of `mode`
+    pub fn for_mode(mode: &GameMode) -> Self {
+        match mode {
+            GameMode::Classic | GameMode::Infection { .. } | GameMode::Elimination { .. } => {
+                Self::default()
+            }
+            GameMode::FreezeTag => Self {
+                not_it_behaviour: "rescue_frozen".to_string(),
+                ..Self::default()
+            },
+            GameMode::CaptureTheFlag { .. } => Self::new("guard_flag", "capture_flag"),
+        }
+    }
+}
+
+/// Simulated time in milliseconds since the simulation started.
+/// It only advances with ticks, never with the wall-clock.
 #[derive(Default, Component)]
 pub struct Time(pub u128);
 
+/// Number of ticks simulated so far
+#[derive(Default, Component)]
+pub struct Tick(pub u64);
+
 /// Total number of tags that has happened
 #[derive(Default, Component)]
 pub struct TagCount(pub u64);
 
+/// Number of times a player's behaviour was not found in the
+/// [crate::behaviours::BehaviourRegistry], the player then kept its course
+#[derive(Default, Component)]
+pub struct UnresolvedBehaviours(pub u64);
+
+/// Number of susceptible, infected and recovered players at the end of a tick
+#[derive(Clone, Debug)]
+pub struct SirSample {
+    pub tick: u64,
+    /// Simulated milliseconds
+    pub time: u128,
+    pub susceptible: usize,
+    pub infected: usize,
+    pub recovered: usize,
+}
+
+/// Time series of the spread of "it" in [GameMode::Infection], one sample per tick
+#[derive(Clone, Default, Debug, Component)]
+pub struct EpidemicCurve(pub Vec<SirSample>);
+
+impl EpidemicCurve {
+    /// Write the curve as CSV, with a header row
+    pub fn write_csv<W: std::io::Write>(&self, mut writer: W) -> std::io::Result<()> {
+        writeln!(writer, "tick,time_ms,susceptible,infected,recovered")?;
+        for sample in self.0.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{},{}",
+                sample.tick, sample.time, sample.susceptible, sample.infected, sample.recovered
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Identifies a player across rounds, as entities are spawned anew every round
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Component)]
+pub struct PlayerId(pub usize);
+
+/// The team a player belongs to in [GameMode::CaptureTheFlag]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Component)]
+pub struct Team(pub usize);
+
+/// The flag of a team, an entity of its own with a [Position].
+/// It is carried along by the enemy player who picked it up, if any.
+#[derive(Clone, Debug, Component)]
+pub struct Flag {
+    pub team: usize,
+    pub carrier: Option<EntityId>,
+}
+
+/// Flags every team has brought home in the current round, indexed by [Team]
+#[derive(Clone, Default, Debug, Component)]
+pub struct TeamScores(pub Vec<u32>);
+
+/// The part of the arena currently in play, see [crate::SimConfig::shrink]
+#[derive(Clone, Debug, Component)]
+pub struct PlayArea(pub Zone);
+
+/// Number of ticks the player has spent as "it"
+#[derive(Default, Component)]
+pub struct TimeAsIt(pub u64);
+
+/// An "it" player touching another player during the current tick
+#[derive(Clone, Debug)]
+pub struct Contact {
+    pub it: EntityId,
+    pub other: EntityId,
+    pub distance: f32,
+    /// Whether `other` could be tagged by `it`
+    pub taggable: bool,
+}
+
+/// Every contact of "it" players found in the current tick
+#[derive(Default, Component)]
+pub struct Contacts(pub Vec<Contact>);
+
 /// Has the player been tagged recently? At what timestamp if so
 #[derive(Default, Component)]
 pub struct RecentlyTagged(pub Option<u128>);
 
+/// When the player entered the base it is in, if any.
+/// Players who stayed too long are evicted, and can be tagged in any base until the
+/// eviction has run out.
+#[derive(Clone, Default, Debug, Component)]
+pub struct BaseDwell {
+    pub entered: Option<u128>,
+    /// Last time the player was turned out of a base
+    pub evicted: Option<u128>,
+}
+
+impl BaseDwell {
+    /// Whether the player is in a base and cannot be tagged
+    pub fn is_safe(&self) -> bool {
+        self.entered.is_some() && self.evicted.is_none()
+    }
+}
+
 /// A player has endurance that changes each tick
 /// First value is current, second is max endurance
 #[derive(Default, Component)]
 pub struct Endurance(pub u16, pub u16);
-impl Distribution<Endurance> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Endurance {
-        let initial = rng.gen_range(600..800);
-        Endurance(initial, initial)
+
+/// Random number generator of the `world`, used for decisions that are not tied to a player.
+/// Seeded from [crate::SimConfig::seed] so that runs can be reproduced.
+#[derive(Component)]
+pub struct SimRng(pub ChaCha8Rng);
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self(ChaCha8Rng::seed_from_u64(seed))
+    }
+}
+
+/// Every player draws from its own random stream.
+/// This keeps results independent of the order in which systems are scheduled.
+#[derive(Component)]
+pub struct PlayerRng(pub ChaCha8Rng);
+
+impl PlayerRng {
+    /// Stream `0` is reserved for [SimRng], players use the streams after it.
+    pub fn new(seed: u64, player_index: u64) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        rng.set_stream(player_index + 1);
+        Self(rng)
     }
 }
 
@@ -215,6 +377,8 @@ pub struct RTreeData {
     pub velocity: Velocity,
     pub recently_tagged: bool,
     pub tagged: Tagged,
+    /// Only set in [GameMode::CaptureTheFlag]
+    pub team: Option<usize>,
 }
 impl spade::SpatialObject for RTreeData {
     type Point = Geo2D;
@@ -223,18 +387,40 @@ impl spade::SpatialObject for RTreeData {
         spade::BoundingRect::from_point(self.position.0)
     }
 
+    /// Plane distance, used by the R*-Tree itself.
+    /// Distances across wrapping edges are handled in [crate::spatial::SpatialQuery].
     fn distance2(&self, point: &Self::Point) -> <Self::Point as spade::PointN>::Scalar {
         self.position.0.distance2(point)
     }
 }
 
-/// Wrap raylib handler in a Shipyard ECS component.
-#[derive(Component)]
-pub struct RLHandle(pub raylib::RaylibHandle);
+/// A static obstacle players cannot pass through
+#[derive(Clone, Debug, Component)]
+pub struct Obstacle(pub Shape);
 
-/// Wrap raylib thread in a Shipyard ECS component.
-#[derive(Component)]
-pub struct RLThread(pub raylib::RaylibThread);
+/// Index of all obstacles, built once as they do not move
+#[derive(Default, Component)]
+pub struct ObstaclesRTree(pub RTree<ObstacleData>);
+
+#[derive(Clone, Debug)]
+pub struct ObstacleData {
+    pub entity_id: EntityId,
+    pub shape: Shape,
+}
+impl spade::SpatialObject for ObstacleData {
+    type Point = Geo2D;
+
+    fn mbr(&self) -> spade::BoundingRect<Self::Point> {
+        let (min, max) = self.shape.bounds();
+        spade::BoundingRect::from_corners(&min, &max)
+    }
+
+    /// Squared distance to the outline of the shape, `0.0` inside it
+    fn distance2(&self, point: &Self::Point) -> <Self::Point as spade::PointN>::Scalar {
+        let distance = self.shape.distance(point);
+        distance * distance
+    }
+}
 
 #[cfg(test)]
 mod test {
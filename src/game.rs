@@ -0,0 +1,411 @@
+use std::time::Duration;
+
+use rand::Rng;
+use shipyard::*;
+
+use crate::{
+    behaviours::{Blackboard, FlockWeights, Steering},
+    spatial::ObstacleQuery,
+    systems::{advance_simulation_clock, tag_initial_random_players},
+    BaseDwell, Endurance, EpidemicCurve, Flag, GameMode, NavPath, ObstaclesRTree, PlayerId,
+    PlayerRng, Position, RecentlyTagged, SimConfig, SimRng, TagState, Tagged, Team, TeamScores,
+    Time, TimeAsIt, Velocity, TICK,
+};
+
+/// Where the match is in its lifecycle
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Component)]
+pub enum GameState {
+    /// Players are placed, waiting for the match to begin
+    Lobby,
+    /// A round is being played
+    Running,
+    /// A win condition has been met, waiting for the next round
+    RoundOver,
+    /// The last round has been played
+    MatchOver,
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        Self::Lobby
+    }
+}
+
+/// Progress of the match
+#[derive(Clone, Default, Debug, Component)]
+pub struct Match {
+    /// The current round, starting at 1
+    pub round: u32,
+    /// Simulated time at which the [GameState] last changed
+    pub state_changed_at: u128,
+    /// Rounds won by every player, indexed by [PlayerId]
+    pub wins: Vec<u32>,
+    /// Ticks every player spent as "it" over all rounds, indexed by [PlayerId]
+    pub ticks_as_it: Vec<u64>,
+}
+
+impl Match {
+    /// Players and their wins, most wins first
+    pub fn standings(&self) -> Vec<(usize, u32)> {
+        let mut standings: Vec<(usize, u32)> = self.wins.iter().cloned().enumerate().collect();
+        standings.sort_by(|(_, a), (_, b)| b.cmp(a));
+        standings
+    }
+}
+
+/// Snapshot of a running round, which [WinCondition]s are checked against
+#[derive(Clone, Default, Debug)]
+pub struct RoundStatus {
+    pub not_it: usize,
+    pub it: usize,
+    pub frozen: usize,
+    pub recovered: usize,
+    pub eliminated: usize,
+    pub jailed: usize,
+    /// Flags brought home by every team, see [TeamScores]
+    pub captures: Vec<u32>,
+    /// Simulated time since the round started
+    pub elapsed: Duration,
+}
+
+/// A rule ending a round once met
+#[derive(Clone, Debug)]
+pub enum WinCondition {
+    /// At most one untagged player is left
+    LastSurvivor,
+    /// The round has lasted this long
+    TimeLimit(Duration),
+    /// Every player who is not "it" is frozen
+    AllFrozen,
+    /// Nobody is infected anymore, or nobody is left to infect
+    InfectionOver,
+    /// A team has brought home this many flags
+    Captures(u32),
+    /// Any other rule
+    Custom(fn(&RoundStatus) -> bool),
+}
+
+impl WinCondition {
+    pub fn is_met(&self, status: &RoundStatus) -> bool {
+        match self {
+            WinCondition::LastSurvivor => status.not_it <= 1,
+            WinCondition::TimeLimit(limit) => status.elapsed >= *limit,
+            WinCondition::AllFrozen => status.not_it == 0,
+            WinCondition::InfectionOver => status.it == 0 || status.not_it == 0,
+            WinCondition::Captures(captures) => status.captures.iter().any(|c| c >= captures),
+            WinCondition::Custom(is_met) => is_met(status),
+        }
+    }
+}
+
+impl GameMode {
+    /// What becomes of a player tagged by an "it" player, `None` when played in teams
+    pub fn tagged_state(&self) -> Option<TagState> {
+        match self {
+            GameMode::Classic | GameMode::Infection { .. } => Some(TagState::It),
+            GameMode::FreezeTag => Some(TagState::Frozen),
+            GameMode::Elimination { .. } => Some(TagState::Eliminated),
+            GameMode::CaptureTheFlag { .. } => None,
+        }
+    }
+
+    /// The conditions ending a round, which are part of the rules of the mode
+    pub fn win_conditions(&self) -> Vec<WinCondition> {
+        match self {
+            GameMode::Classic => vec![],
+            GameMode::FreezeTag => vec![WinCondition::AllFrozen],
+            GameMode::Infection { .. } => vec![WinCondition::InfectionOver],
+            GameMode::Elimination { .. } => vec![WinCondition::LastSurvivor],
+            GameMode::CaptureTheFlag {
+                captures_to_win, ..
+            } => vec![WinCondition::Captures(*captures_to_win)],
+        }
+    }
+}
+
+/// Place a fresh set of players in the arena, and tag the first "it" players.
+/// When playing in teams, players are dealt into teams, each team gets `it_count` guards,
+/// and the flags are placed.
+/// Used when the `world` is initialized, and when a new round starts.
+pub fn spawn_players(mut all_storages: AllStoragesViewMut) {
+    let (players, team_count, it_count) = {
+        let config = all_storages.borrow::<UniqueView<SimConfig>>().unwrap();
+        let obstacles = all_storages.borrow::<UniqueView<ObstaclesRTree>>().unwrap();
+        let obstacles = ObstacleQuery::new(&obstacles.0);
+        let mut sim_rng = all_storages.borrow::<UniqueViewMut<SimRng>>().unwrap();
+        let rng = &mut sim_rng.0;
+
+        // Players are not placed within obstacles, unless the arena is full of them
+        let free_position = |rng: &mut rand_chacha::ChaCha8Rng| {
+            let mut pos = rng.sample::<Position, _>(&*config);
+            for _ in 0..100 {
+                if obstacles.within_radius(&pos, config.player_size).is_empty() {
+                    break;
+                }
+                pos = rng.sample::<Position, _>(&*config);
+            }
+            pos
+        };
+
+        // Behaviours chosen in the config take over the defaults of the game mode
+        let behaviour = config.player_behaviour();
+
+        // Every round gets its own player streams
+        let round_seed: u64 = rng.gen();
+        let players: Vec<_> = (0..config.player_count)
+            .map(|i| {
+                (
+                    free_position(rng),
+                    rng.gen::<Velocity>(),
+                    rng.sample::<Endurance, _>(&*config),
+                    RecentlyTagged::default(),
+                    TimeAsIt::default(),
+                    Tagged::default(),
+                    behaviour.clone(),
+                    PlayerRng::new(round_seed, i as u64),
+                    PlayerId(i),
+                )
+            })
+            .collect();
+        (players, config.team_count(), config.it_count)
+    };
+
+    let entity_ids: Vec<EntityId> = all_storages.bulk_add_entity(players).collect();
+
+    // Every player starts without a path to follow, outside of any base, without steering
+    // and with nothing remembered, flocking as the config says
+    all_storages
+        .run(
+            |entities: EntitiesView,
+             uv_config: UniqueView<SimConfig>,
+             mut vm_nav_path: ViewMut<NavPath>,
+             mut vm_base_dwell: ViewMut<BaseDwell>,
+             (mut vm_steering, mut vm_flock_weights, mut vm_blackboard): (
+                ViewMut<Steering>,
+                ViewMut<FlockWeights>,
+                ViewMut<Blackboard>,
+            )| {
+                for entity_id in entity_ids.iter() {
+                    entities.add_component(
+                        *entity_id,
+                        (
+                            &mut vm_nav_path,
+                            &mut vm_base_dwell,
+                            &mut vm_steering,
+                            &mut vm_flock_weights,
+                            &mut vm_blackboard,
+                        ),
+                        (
+                            NavPath::default(),
+                            BaseDwell::default(),
+                            Steering::default(),
+                            uv_config.flocking.clone(),
+                            Blackboard::default(),
+                        ),
+                    );
+                }
+            },
+        )
+        .expect("players to get a path");
+
+    if team_count == 0 {
+        all_storages
+            .run(tag_initial_random_players)
+            .expect("inital players to be tagged");
+        return;
+    }
+
+    // Players are dealt into teams in turn, the first ones of every team guard its flag
+    all_storages
+        .run(
+            |entities: EntitiesView, mut vm_team: ViewMut<Team>, mut vm_tagged: ViewMut<Tagged>| {
+                for (i, entity_id) in entity_ids.iter().enumerate() {
+                    entities.add_component(*entity_id, &mut vm_team, Team(i % team_count));
+                    if i / team_count < it_count {
+                        if let Ok(mut tagged) = (&mut vm_tagged).get(*entity_id) {
+                            tagged.0 = TagState::It;
+                        }
+                    }
+                }
+            },
+        )
+        .expect("players to be dealt into teams");
+
+    let flags: Vec<(Position, Flag)> = {
+        let config = all_storages.borrow::<UniqueView<SimConfig>>().unwrap();
+        (0..team_count)
+            .map(|team| {
+                (
+                    config.flag_home(team),
+                    Flag {
+                        team,
+                        carrier: None,
+                    },
+                )
+            })
+            .collect()
+    };
+    all_storages.bulk_add_entity(flags).next();
+
+    all_storages
+        .borrow::<UniqueViewMut<TeamScores>>()
+        .unwrap()
+        .0 = vec![0; team_count];
+}
+
+/// Remove every player and flag, then spawn them anew for the next round
+fn restart_round(mut all_storages: AllStoragesViewMut) {
+    let mut removed: Vec<EntityId> = all_storages
+        .borrow::<View<PlayerId>>()
+        .unwrap()
+        .iter()
+        .with_id()
+        .map(|(entity_id, _)| entity_id)
+        .collect();
+    removed.extend(
+        all_storages
+            .borrow::<View<Flag>>()
+            .unwrap()
+            .iter()
+            .with_id()
+            .map(|(entity_id, _)| entity_id),
+    );
+    for entity_id in removed {
+        all_storages.delete_entity(entity_id);
+    }
+    // The curve follows the spread within the round being played
+    all_storages
+        .borrow::<UniqueViewMut<EpidemicCurve>>()
+        .unwrap()
+        .0
+        .clear();
+
+    spawn_players(all_storages);
+}
+
+/// End the round when one of the win conditions of the mode or the config is met.
+/// Players who are untagged at the end win the round, or the "it" players if nobody escaped.
+/// When playing in teams, every player of the team with the most captures wins instead,
+/// and nobody wins a round tied between teams.
+fn check_win_conditions(
+    uv_config: UniqueView<SimConfig>,
+    uv_time: UniqueView<Time>,
+    uv_team_scores: UniqueView<TeamScores>,
+    (v_tagged, v_team, v_player_id): (View<Tagged>, View<Team>, View<PlayerId>),
+    mut uvm_match: UniqueViewMut<Match>,
+    mut uvm_game_state: UniqueViewMut<GameState>,
+) {
+    let mut status = RoundStatus {
+        elapsed: Duration::from_millis((uv_time.0 - uvm_match.state_changed_at) as u64),
+        captures: uv_team_scores.0.clone(),
+        ..RoundStatus::default()
+    };
+    for tag in v_tagged.iter() {
+        match tag.0 {
+            TagState::NotIt => status.not_it += 1,
+            TagState::It => status.it += 1,
+            TagState::Frozen => status.frozen += 1,
+            TagState::Recovered => status.recovered += 1,
+            TagState::Eliminated => status.eliminated += 1,
+            TagState::Jailed => status.jailed += 1,
+        }
+    }
+
+    let round_over = uv_config
+        .game_mode
+        .win_conditions()
+        .iter()
+        .chain(uv_config.win_conditions.iter())
+        .any(|condition| condition.is_met(&status));
+    if !round_over {
+        return;
+    }
+
+    let winning_state = if status.not_it > 0 {
+        TagState::NotIt
+    } else {
+        TagState::It
+    };
+    let most_captures = status.captures.iter().max();
+    let leaders: Vec<usize> = (0..status.captures.len())
+        .filter(|team| Some(&status.captures[*team]) == most_captures)
+        .collect();
+    let winning_team = match leaders.as_slice() {
+        [team] => Some(*team),
+        _ => None,
+    };
+    let in_teams = !status.captures.is_empty();
+    for (entity_id, (tag, player_id)) in (&v_tagged, &v_player_id).iter().with_id() {
+        let won = if in_teams {
+            v_team
+                .get(entity_id)
+                .map_or(false, |team| Some(team.0) == winning_team)
+        } else {
+            tag.0 == winning_state
+        };
+        if won {
+            if uvm_match.wins.len() <= player_id.0 {
+                uvm_match.wins.resize(player_id.0 + 1, 0);
+            }
+            uvm_match.wins[player_id.0] += 1;
+        }
+    }
+
+    *uvm_game_state = GameState::RoundOver;
+    uvm_match.state_changed_at = uv_time.0;
+}
+
+/// Advance the world by one tick, following the match lifecycle.
+/// The [TICK] workload only runs while a round is being played, the simulated clock
+/// keeps running in between rounds.
+pub fn step(world: &World) {
+    let state = *world.borrow::<UniqueView<GameState>>().unwrap();
+    if state == GameState::Running {
+        world.run_workload(TICK).unwrap();
+        world.run(check_win_conditions).unwrap();
+        return;
+    }
+
+    world.run(advance_simulation_clock).unwrap();
+
+    // Read what is needed up front, restarting a round borrows the whole `world`
+    let (lobby_duration, intermission, rounds, time, state_changed_at, round) = {
+        let (uv_config, uv_time, uv_match) = world
+            .borrow::<(UniqueView<SimConfig>, UniqueView<Time>, UniqueView<Match>)>()
+            .unwrap();
+        (
+            uv_config.lobby_duration,
+            uv_config.intermission,
+            uv_config.rounds,
+            uv_time.0,
+            uv_match.state_changed_at,
+            uv_match.round,
+        )
+    };
+
+    let waited = Duration::from_millis((time - state_changed_at) as u64);
+    let next_state = match state {
+        GameState::Lobby if waited >= lobby_duration => GameState::Running,
+        GameState::RoundOver if waited >= intermission => {
+            if round >= rounds {
+                GameState::MatchOver
+            } else {
+                world.run(restart_round).unwrap();
+                GameState::Running
+            }
+        }
+        _ => return,
+    };
+
+    world
+        .run(
+            |mut uvm_game_state: UniqueViewMut<GameState>, mut uvm_match: UniqueViewMut<Match>| {
+                if next_state == GameState::Running {
+                    uvm_match.round += 1;
+                }
+                *uvm_game_state = next_state;
+                uvm_match.state_changed_at = time;
+            },
+        )
+        .unwrap();
+}
@@ -0,0 +1,128 @@
+use std::fmt;
+use std::time::Duration;
+
+use shipyard::*;
+
+use crate::{
+    behaviours::BehaviourRegistry, initialize_world_with_registry, step, EpidemicCurve, GameState,
+    Match, SimConfig, TagCount, Time, UnresolvedBehaviours,
+};
+
+/// Outcome of a headless run, printed when the run is over.
+#[derive(Debug)]
+pub struct RunSummary {
+    pub ticks: u64,
+    /// Where the match was when the run stopped, [GameState::MatchOver] if it stopped early
+    pub game_state: GameState,
+    /// Rounds started during the run
+    pub rounds: u32,
+    /// Players and their round wins, most wins first
+    pub standings: Vec<(usize, u32)>,
+    /// Simulated milliseconds that passed during the run
+    pub simulated_millis: u128,
+    pub total_tags: u64,
+    /// Times a player's behaviour was not registered, see [UnresolvedBehaviours]
+    pub unresolved_behaviours: u64,
+    /// Simulated time each player spent as "it" over all rounds, by [crate::PlayerId],
+    /// longest first
+    pub time_as_it: Vec<(usize, Duration)>,
+    /// Spread of "it" over time, only recorded in [crate::GameMode::Infection]
+    pub epidemic_curve: EpidemicCurve,
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Simulated {} ticks ({:.1} s)",
+            self.ticks,
+            self.simulated_millis as f64 / 1000.0
+        )?;
+        writeln!(f, "Round {}: {:?}", self.rounds, self.game_state)?;
+        for (player_id, wins) in self.standings.iter().filter(|(_, wins)| *wins > 0) {
+            writeln!(f, "  player {:>6}: {:>4} wins", player_id, wins)?;
+        }
+        writeln!(f, "Total taggings: {}", self.total_tags)?;
+        if self.unresolved_behaviours > 0 {
+            writeln!(
+                f,
+                "Behaviours not registered: {} times a player kept its course",
+                self.unresolved_behaviours
+            )?;
+        }
+        if let Some(last) = self.epidemic_curve.0.last() {
+            writeln!(
+                f,
+                "Susceptible: {}, infected: {}, recovered: {}",
+                last.susceptible, last.infected, last.recovered
+            )?;
+        }
+        writeln!(f, "Time spent as it:")?;
+        for (player_id, duration) in self.time_as_it.iter().filter(|(_, d)| !d.is_zero()) {
+            writeln!(
+                f,
+                "  player {:>6}: {:>10.1} s",
+                player_id,
+                duration.as_secs_f64()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Run the simulation for `ticks` ticks without any rendering, or until the match is over.
+/// Only [step] is run, so no window or raylib handles are needed.
+pub fn run_headless(config: SimConfig, ticks: u64) -> RunSummary {
+    run_headless_with_registry(config, BehaviourRegistry::default(), ticks)
+}
+
+/// Like [run_headless], with the behaviours in `registry`
+pub fn run_headless_with_registry(
+    config: SimConfig,
+    registry: BehaviourRegistry,
+    ticks: u64,
+) -> RunSummary {
+    let tick_micros = config.tick_duration.as_micros() as u64;
+    let world = initialize_world_with_registry(config, registry);
+
+    let mut ticks_run = 0;
+    while ticks_run < ticks
+        && *world.borrow::<UniqueView<GameState>>().unwrap() != GameState::MatchOver
+    {
+        step(&world);
+        ticks_run += 1;
+    }
+
+    world
+        .run(
+            |uv_time: UniqueView<Time>,
+             uv_game_state: UniqueView<GameState>,
+             uv_match: UniqueView<Match>,
+             uv_tag_count: UniqueView<TagCount>,
+             uv_unresolved: UniqueView<UnresolvedBehaviours>,
+             uv_epidemic_curve: UniqueView<EpidemicCurve>| {
+                let mut time_as_it: Vec<(usize, Duration)> = uv_match
+                    .ticks_as_it
+                    .iter()
+                    .enumerate()
+                    .map(|(player_id, ticks)| {
+                        (player_id, Duration::from_micros(ticks * tick_micros))
+                    })
+                    .collect();
+                time_as_it.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+                RunSummary {
+                    ticks: ticks_run,
+                    game_state: *uv_game_state,
+                    rounds: uv_match.round,
+                    standings: uv_match.standings(),
+                    simulated_millis: uv_time.0,
+                    total_tags: uv_tag_count.0,
+                    unresolved_behaviours: uv_unresolved.0,
+                    time_as_it,
+                    epidemic_curve: EpidemicCurve(uv_epidemic_curve.0.clone()),
+                }
+            },
+        )
+        .unwrap()
+}
@@ -1,48 +1,491 @@
-use rand::prelude::*;
 use shipyard::*;
 
+pub mod arena;
 pub mod behaviours;
+pub mod config;
 pub mod entities_components;
+pub mod game;
+pub mod headless;
+pub mod navigation;
+#[cfg(feature = "render")]
+pub mod render;
+pub mod spatial;
 pub mod systems;
+pub use crate::config::*;
 pub use crate::entities_components::*;
+pub use crate::game::*;
+pub use crate::headless::*;
+pub use crate::navigation::{NavGrid, NavPath};
 pub use crate::systems::*;
 
-const PLAYER_SIZE: f32 = 10.0;
+use crate::arena::TerrainLayer;
+use crate::behaviours::BehaviourRegistry;
 
-/// Width of the `world` view
-pub const WIDTH: i32 = 1024;
-/// Height of the `world` view
-pub const HEIGHT: i32 = 1024;
+/// A `world` playing with the built-in behaviours
+pub fn initialize_world(config: SimConfig) -> World {
+    initialize_world_with_registry(config, BehaviourRegistry::default())
+}
 
-pub fn initialize_world(player_count: usize) -> World {
+/// A `world` whose players can take on the behaviours in `registry`.
+/// Players whose behaviour is not registered keep their course and are counted in
+/// [UnresolvedBehaviours], [SimConfig::check_behaviours] finds them beforehand.
+pub fn initialize_world_with_registry(config: SimConfig, registry: BehaviourRegistry) -> World {
     let mut world = World::default();
-    // Time is kept and updated after every frame/tick
+    // Simulated time is kept and advanced after every tick
+    world.add_unique(Tick::default()).unwrap();
     world.add_unique(Time::default()).unwrap();
     // Total number of tags shown in view
     world.add_unique(TagCount::default()).unwrap();
+    // Behaviours players asked for that are not registered
+    world.add_unique(UnresolvedBehaviours::default()).unwrap();
+    // Lifecycle of the match, and its rounds
+    world.add_unique(GameState::default()).unwrap();
+    world.add_unique(Match::default()).unwrap();
+    // Flags brought home by every team, in capture the flag
+    world.add_unique(TeamScores::default()).unwrap();
+    // Spread of "it" over time, recorded in infection tag
+    world.add_unique(EpidemicCurve::default()).unwrap();
     // R*-Tree over all players used each frame
     world.add_unique(PlayersPositionRTree::default()).unwrap();
-
-    let mut rng = rand::thread_rng();
+    // Players touching "it" players, found while tagging
+    world.add_unique(Contacts::default()).unwrap();
+    // R*-Tree over the static obstacles
+    world.add_unique(ObstaclesRTree::default()).unwrap();
+    // The whole arena is in play until it starts shrinking
+    world.add_unique(PlayArea(config.arena())).unwrap();
 
     world
-        .bulk_add_entity((0..player_count).map(|_| {
-            (
-                rng.gen::<Position>(),
-                rng.gen::<Velocity>(),
-                rng.gen::<Endurance>(),
-                RecentlyTagged::default(),
-                Tagged::default(),
-                PlayerBehaviour::default(),
-            )
-        }))
+        .bulk_add_entity(
+            config
+                .obstacles
+                .iter()
+                .cloned()
+                .map(|shape| (Obstacle(shape),)),
+        )
         .next();
+    world.run(index_obstacles).unwrap();
+    // Grid of the cells players can walk through, built from the obstacles
+    world.add_unique(NavGrid::default()).unwrap();
 
-    world
-        .run(tag_initial_random_player)
-        .expect("one inital player to be tagged");
+    // Behaviours players refer to by name
+    world.add_unique(registry).unwrap();
+
+    // Random decisions that are not tied to a single player
+    world.add_unique(SimRng::new(config.seed)).unwrap();
+    // Simulation parameters read by every system
+    world.add_unique(config).unwrap();
+
+    world.run(build_nav_grid).unwrap();
+    // Mud, ice and rest zones, sampled under every player each tick
+    world.add_unique(TerrainLayer::default()).unwrap();
+    world.run(build_terrain_layer).unwrap();
+
+    world.run(spawn_players).unwrap();
 
     register_workloads(&world);
 
     world
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use shipyard::{UniqueView, View, ViewMut, World};
+
+    use crate::arena::Shape;
+    use crate::{
+        initialize_world, run_headless, step, EpidemicCurve, GameMode, GameState, Match,
+        OutsidePenalty, PlayerId, Position, ShrinkSchedule, SimConfig, TagCount, TagState, Tagged,
+        TeamScores, Time, Velocity, WinCondition, TICK,
+    };
+
+    /// Move players, by [PlayerId], to the given positions
+    fn place_players(world: &World, placements: &[(usize, Position)]) {
+        world
+            .run(
+                |v_player_id: View<PlayerId>, mut vm_position: ViewMut<Position>| {
+                    for (player_id, pos) in (&v_player_id, &mut vm_position).iter() {
+                        if let Some((_, new_pos)) =
+                            placements.iter().find(|(i, _)| *i == player_id.0)
+                        {
+                            *pos = new_pos.clone();
+                        }
+                    }
+                },
+            )
+            .unwrap();
+    }
+
+    /// Give players, by [PlayerId], the given tag states
+    fn tag_players(world: &World, tags: &[(usize, TagState)]) {
+        world
+            .run(
+                |v_player_id: View<PlayerId>, mut vm_tagged: ViewMut<Tagged>| {
+                    for (player_id, tag) in (&v_player_id, &mut vm_tagged).iter() {
+                        if let Some((_, new_tag)) = tags.iter().find(|(i, _)| *i == player_id.0) {
+                            tag.0 = new_tag.clone();
+                        }
+                    }
+                },
+            )
+            .unwrap();
+    }
+
+    fn tag_of(world: &World, player: usize) -> TagState {
+        world
+            .run(|v_player_id: View<PlayerId>, v_tagged: View<Tagged>| {
+                (&v_player_id, &v_tagged)
+                    .iter()
+                    .find(|(player_id, _)| player_id.0 == player)
+                    .map(|(_, tag)| tag.0.clone())
+                    .unwrap()
+            })
+            .unwrap()
+    }
+
+    fn position_of(world: &World, player: usize) -> Position {
+        world
+            .run(|v_player_id: View<PlayerId>, v_position: View<Position>| {
+                (&v_player_id, &v_position)
+                    .iter()
+                    .find(|(player_id, _)| player_id.0 == player)
+                    .map(|(_, pos)| pos.clone())
+                    .unwrap()
+            })
+            .unwrap()
+    }
+
+    /// Two teams of two, the left half of the arena is the territory of team 0.
+    /// Players 0 and 2 are in team 0, players 1 and 3 in team 1.
+    fn capture_the_flag() -> SimConfig {
+        SimConfig {
+            player_count: 4,
+            game_mode: GameMode::CaptureTheFlag {
+                teams: 2,
+                captures_to_win: 3,
+            },
+            ..SimConfig::default()
+        }
+    }
+
+    fn positions_after(config: SimConfig, ticks: usize) -> Vec<Position> {
+        let world = initialize_world(config);
+        for _ in 0..ticks {
+            world.run_workload(TICK).unwrap();
+        }
+        world
+            .run(|v_position: View<Position>| v_position.iter().cloned().collect())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_same_seed_same_trajectories() {
+        let config = SimConfig {
+            seed: 42,
+            player_count: 200,
+            ..SimConfig::default()
+        };
+
+        assert_eq!(
+            positions_after(config.clone(), 100),
+            positions_after(config, 100)
+        );
+    }
+
+    #[test]
+    fn test_no_tags_in_base() {
+        // The whole arena is a base
+        let world = initialize_world(SimConfig {
+            player_count: 200,
+            safe_zones: vec![Shape::Box {
+                min: [-1.0, -1.0],
+                max: [1025.0, 1025.0],
+            }],
+            ..SimConfig::default()
+        });
+        for _ in 0..100 {
+            world.run_workload(TICK).unwrap();
+        }
+
+        assert_eq!(world.borrow::<UniqueView<TagCount>>().unwrap().0, 0);
+    }
+
+    #[test]
+    fn test_evicted_player_tagged_back_in_base() {
+        let base = Shape::Box {
+            min: [100.0, 100.0],
+            max: [300.0, 300.0],
+        };
+        let world = initialize_world(SimConfig {
+            player_count: 2,
+            safe_zones: vec![base.clone()],
+            max_base_dwell: Some(Duration::from_millis(100)),
+            ..SimConfig::default()
+        });
+        tag_players(&world, &[(0, TagState::It), (1, TagState::NotIt)]);
+        place_players(
+            &world,
+            &[
+                (0, Position::new(800.0, 800.0)),
+                (1, Position::new(200.0, 200.0)),
+            ],
+        );
+
+        // Player 1 stays too long and is turned out of the base
+        for _ in 0..200 {
+            world.run_workload(TICK).unwrap();
+            if !base.contains(&position_of(&world, 1).0) {
+                break;
+            }
+        }
+        assert!(!base.contains(&position_of(&world, 1).0));
+
+        // Right back in the base, it is still fair game
+        place_players(
+            &world,
+            &[
+                (0, Position::new(205.0, 200.0)),
+                (1, Position::new(200.0, 200.0)),
+            ],
+        );
+        world.run_workload(TICK).unwrap();
+        assert_eq!(tag_of(&world, 1), TagState::It);
+    }
+
+    #[test]
+    fn test_tagged_outside_play_area() {
+        // The play area is gone as soon as the round begins
+        let world = initialize_world(SimConfig {
+            game_mode: GameMode::Elimination {
+                despawn_tagged: false,
+            },
+            shrink: Some(ShrinkSchedule {
+                delay: Duration::from_secs(0),
+                duration: Duration::from_secs(0),
+                final_scale: 0.0,
+                penalty: OutsidePenalty::Tag,
+            }),
+            ..SimConfig::default()
+        });
+        world.run_workload(TICK).unwrap();
+
+        let tags: Vec<TagState> = world
+            .run(|v_tagged: View<Tagged>| v_tagged.iter().map(|tag| tag.0.clone()).collect())
+            .unwrap();
+        assert!(!tags.contains(&TagState::NotIt));
+    }
+
+    #[test]
+    fn test_steered_players_keep_their_speed() {
+        // Wandering players ask for a new heading every tick, but never for a speed
+        let world = initialize_world(SimConfig {
+            player_count: 50,
+            it_behaviour: Some("wander_it".to_string()),
+            not_it_behaviour: Some("wander".to_string()),
+            ..SimConfig::default()
+        });
+        for _ in 0..200 {
+            world.run_workload(TICK).unwrap();
+        }
+
+        let speeds: Vec<f32> = world
+            .run(|v_velocity: View<Velocity>| {
+                v_velocity.iter().map(|vel| vel.magnitude()).collect()
+            })
+            .unwrap();
+        assert!(speeds.iter().all(|speed| (speed - 2.0).abs() < 1e-3));
+    }
+
+    #[test]
+    fn test_frozen_and_freed_by_contact() {
+        let world = initialize_world(SimConfig {
+            player_count: 3,
+            game_mode: GameMode::FreezeTag,
+            ..SimConfig::default()
+        });
+        tag_players(
+            &world,
+            &[
+                (0, TagState::It),
+                (1, TagState::NotIt),
+                (2, TagState::NotIt),
+            ],
+        );
+
+        // "It" catches player 1
+        place_players(
+            &world,
+            &[
+                (0, Position::new(200.0, 200.0)),
+                (1, Position::new(205.0, 200.0)),
+                (2, Position::new(800.0, 800.0)),
+            ],
+        );
+        world.run_workload(TICK).unwrap();
+        assert_eq!(tag_of(&world, 1), TagState::Frozen);
+
+        // Once "it" is gone, player 2 touches player 1
+        let frozen = position_of(&world, 1);
+        place_players(
+            &world,
+            &[
+                (0, Position::new(800.0, 800.0)),
+                (2, Position::new(frozen.0[0] + 5.0, frozen.0[1])),
+            ],
+        );
+        world.run_workload(TICK).unwrap();
+        assert_eq!(tag_of(&world, 1), TagState::NotIt);
+    }
+
+    #[test]
+    fn test_intruder_jailed_and_freed() {
+        let config = capture_the_flag();
+        let jail = config.jail(0);
+        let world = initialize_world(config);
+
+        // Player 3 walks right into player 0 in the territory of team 0
+        place_players(
+            &world,
+            &[
+                (0, Position::new(200.0, 300.0)),
+                (1, Position::new(900.0, 100.0)),
+                (2, Position::new(100.0, 100.0)),
+                (3, Position::new(205.0, 300.0)),
+            ],
+        );
+        world.run_workload(TICK).unwrap();
+        assert_eq!(tag_of(&world, 3), TagState::Jailed);
+        assert_eq!(position_of(&world, 3), jail);
+
+        // Its teammate comes to the jail
+        place_players(&world, &[(1, Position::new(jail.0[0] + 5.0, jail.0[1]))]);
+        world.run_workload(TICK).unwrap();
+        assert_eq!(tag_of(&world, 3), TagState::NotIt);
+    }
+
+    #[test]
+    fn test_flag_captured() {
+        let config = capture_the_flag();
+        let flag = config.flag_home(0);
+        let world = initialize_world(config);
+
+        // Player 3 reaches the flag of team 0, away from its defenders
+        place_players(
+            &world,
+            &[
+                (0, Position::new(100.0, 100.0)),
+                (1, Position::new(900.0, 100.0)),
+                (2, Position::new(100.0, 900.0)),
+                (3, Position::new(flag.0[0] + 3.0, flag.0[1])),
+            ],
+        );
+        world.run_workload(TICK).unwrap();
+        assert_eq!(
+            world.borrow::<UniqueView<TeamScores>>().unwrap().0,
+            vec![0, 0]
+        );
+
+        // And brings it into the territory of team 1
+        place_players(&world, &[(3, Position::new(600.0, flag.0[1]))]);
+        world.run_workload(TICK).unwrap();
+        assert_eq!(
+            world.borrow::<UniqueView<TeamScores>>().unwrap().0,
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn test_tied_teams_do_not_win() {
+        // Nobody can capture a flag in the few ticks of the round
+        let world = initialize_world(SimConfig {
+            lobby_duration: Duration::from_secs(0),
+            win_conditions: vec![WinCondition::TimeLimit(Duration::from_millis(100))],
+            ..capture_the_flag()
+        });
+        for _ in 0..100 {
+            if *world.borrow::<UniqueView<GameState>>().unwrap() == GameState::RoundOver {
+                break;
+            }
+            step(&world);
+        }
+
+        assert_eq!(
+            *world.borrow::<UniqueView<GameState>>().unwrap(),
+            GameState::RoundOver
+        );
+        let uv_match = world.borrow::<UniqueView<Match>>().unwrap();
+        assert!(uv_match.wins.iter().all(|wins| *wins == 0));
+    }
+
+    #[test]
+    fn test_epidemic_curve_of_the_round() {
+        let world = initialize_world(SimConfig {
+            player_count: 60,
+            game_mode: GameMode::Infection {
+                recovery: Some(Duration::from_millis(200)),
+            },
+            rounds: 2,
+            intermission: Duration::from_secs(0),
+            win_conditions: vec![WinCondition::TimeLimit(Duration::from_millis(500))],
+            ..SimConfig::default()
+        });
+        for _ in 0..1000 {
+            if world.borrow::<UniqueView<Match>>().unwrap().round == 2 {
+                break;
+            }
+            step(&world);
+        }
+        let second_round_start = world
+            .borrow::<UniqueView<Match>>()
+            .unwrap()
+            .state_changed_at;
+        for _ in 0..10 {
+            step(&world);
+        }
+
+        // Only samples of the second round are left, and every player is in one group
+        let uv_curve = world.borrow::<UniqueView<EpidemicCurve>>().unwrap();
+        assert!(uv_curve.0[0].time > second_round_start);
+        assert!(uv_curve
+            .0
+            .iter()
+            .all(|sample| sample.susceptible + sample.infected + sample.recovered == 60));
+    }
+
+    #[test]
+    fn test_time_as_it_over_all_rounds() {
+        // Somebody is always "it" in classic tag, for at least 200 ms in each of two rounds
+        let summary = run_headless(
+            SimConfig {
+                player_count: 20,
+                rounds: 2,
+                intermission: Duration::from_secs(0),
+                win_conditions: vec![WinCondition::TimeLimit(Duration::from_millis(200))],
+                ..SimConfig::default()
+            },
+            1000,
+        );
+
+        assert_eq!(summary.game_state, GameState::MatchOver);
+        assert!(summary
+            .time_as_it
+            .iter()
+            .all(|(player_id, _)| *player_id < 20));
+        let total: Duration = summary.time_as_it.iter().map(|(_, d)| *d).sum();
+        assert!(total >= Duration::from_millis(390));
+    }
+
+    #[test]
+    fn test_simulated_clock_follows_ticks() {
+        let world = initialize_world(SimConfig {
+            tick_duration: Duration::from_millis(10),
+            ..SimConfig::default()
+        });
+        for _ in 0..250 {
+            world.run_workload(TICK).unwrap();
+        }
+
+        assert_eq!(world.borrow::<UniqueView<Time>>().unwrap().0, 2500);
+    }
+}
@@ -0,0 +1,269 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use shipyard::Component;
+
+use crate::entities_components::Position;
+use crate::spatial::ObstacleQuery;
+
+/// Cost of a straight step between cells, diagonal steps cost `14`
+const STRAIGHT: u32 = 10;
+const DIAGONAL: u32 = 14;
+
+/// The arena divided into square cells, which are blocked if a player standing in the
+/// middle would touch an obstacle. Paths are searched over the open cells with A*.
+/// Built once from the obstacles, like the [crate::ObstaclesRTree].
+#[derive(Clone, Default, Debug, Component)]
+pub struct NavGrid {
+    pub cell_size: f32,
+    pub cols: usize,
+    pub rows: usize,
+    /// Row-major, `true` for blocked cells
+    blocked: Vec<bool>,
+    /// Whether any cell is blocked, looked up by every player every tick
+    any_blocked: bool,
+}
+
+impl NavGrid {
+    pub fn new(
+        width: f32,
+        height: f32,
+        cell_size: f32,
+        player_size: f32,
+        obstacles: ObstacleQuery,
+    ) -> Self {
+        let cols = (width / cell_size).ceil().max(1.0) as usize;
+        let rows = (height / cell_size).ceil().max(1.0) as usize;
+        let open = Self::from_blocked(cell_size, cols, rows, vec![false; cols * rows]);
+        let blocked = (0..cols * rows)
+            .map(|cell| {
+                !obstacles
+                    .within_radius(&open.center(cell), player_size)
+                    .is_empty()
+            })
+            .collect();
+        Self::from_blocked(cell_size, cols, rows, blocked)
+    }
+
+    /// A grid of `cols` by `rows` cells, `blocked` row-major
+    pub fn from_blocked(cell_size: f32, cols: usize, rows: usize, blocked: Vec<bool>) -> Self {
+        let any_blocked = blocked.iter().any(|blocked| *blocked);
+        Self {
+            cell_size,
+            cols,
+            rows,
+            blocked,
+            any_blocked,
+        }
+    }
+
+    /// Whether any cell is blocked, paths are straight lines otherwise
+    pub fn has_blocked(&self) -> bool {
+        self.any_blocked
+    }
+
+    fn cell_at(&self, pos: &Position) -> usize {
+        let col = ((pos.0[0] / self.cell_size) as isize).clamp(0, self.cols as isize - 1);
+        let row = ((pos.0[1] / self.cell_size) as isize).clamp(0, self.rows as isize - 1);
+        row as usize * self.cols + col as usize
+    }
+
+    fn center(&self, cell: usize) -> Position {
+        Position::new(
+            ((cell % self.cols) as f32 + 0.5) * self.cell_size,
+            ((cell / self.cols) as f32 + 0.5) * self.cell_size,
+        )
+    }
+
+    /// The open cell closest to `pos`, searched in growing rings around it
+    fn nearest_open_cell(&self, pos: &Position) -> Option<usize> {
+        let start = self.cell_at(pos);
+        if !self.blocked[start] {
+            return Some(start);
+        }
+        let (col, row) = ((start % self.cols) as isize, (start / self.cols) as isize);
+        for ring in 1..self.cols.max(self.rows) as isize {
+            let mut closest: Option<(f32, usize)> = None;
+            for dy in -ring..=ring {
+                for dx in -ring..=ring {
+                    if dx.abs() != ring && dy.abs() != ring {
+                        continue;
+                    }
+                    let (c, r) = (col + dx, row + dy);
+                    if c < 0 || r < 0 || c >= self.cols as isize || r >= self.rows as isize {
+                        continue;
+                    }
+                    let cell = r as usize * self.cols + c as usize;
+                    let distance = pos.distance_to(&self.center(cell));
+                    if !self.blocked[cell] && closest.map_or(true, |(d, _)| distance < d) {
+                        closest = Some((distance, cell));
+                    }
+                }
+            }
+            if let Some((_, cell)) = closest {
+                return Some(cell);
+            }
+        }
+        None
+    }
+
+    /// The centre of the open cell closest to `pos`
+    pub fn nearest_open(&self, pos: &Position) -> Option<Position> {
+        self.nearest_open_cell(pos).map(|cell| self.center(cell))
+    }
+
+    /// Open cells next to `cell`. Diagonal steps are only taken if both cells
+    /// beside them are open, so paths do not cut corners of obstacles.
+    fn neighbours(&self, cell: usize) -> Vec<(usize, u32)> {
+        let (col, row) = ((cell % self.cols) as isize, (cell / self.cols) as isize);
+        let open = |c: isize, r: isize| {
+            c >= 0
+                && r >= 0
+                && c < self.cols as isize
+                && r < self.rows as isize
+                && !self.blocked[r as usize * self.cols + c as usize]
+        };
+
+        let mut neighbours = Vec::with_capacity(8);
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if (dx, dy) == (0, 0) || !open(col + dx, row + dy) {
+                    continue;
+                }
+                let cost = if dx != 0 && dy != 0 {
+                    if !open(col + dx, row) || !open(col, row + dy) {
+                        continue;
+                    }
+                    DIAGONAL
+                } else {
+                    STRAIGHT
+                };
+                neighbours.push(((row + dy) as usize * self.cols + (col + dx) as usize, cost));
+            }
+        }
+        neighbours
+    }
+
+    /// Octile distance between two cells, never more than the cost of a path between them
+    fn heuristic(&self, from: usize, to: usize) -> u32 {
+        let dx = ((from % self.cols) as isize - (to % self.cols) as isize).abs() as u32;
+        let dy = ((from / self.cols) as isize - (to / self.cols) as isize).abs() as u32;
+        STRAIGHT * u32::max(dx, dy) + (DIAGONAL - STRAIGHT) * u32::min(dx, dy)
+    }
+
+    /// Cell centres leading from `from` to `to` with A*, ending at `to` itself.
+    /// `None` if `to` cannot be reached.
+    pub fn find_path(&self, from: &Position, to: &Position) -> Option<Vec<Position>> {
+        let start = self.nearest_open_cell(from)?;
+        let goal = self.nearest_open_cell(to)?;
+
+        let mut cost = vec![u32::MAX; self.blocked.len()];
+        let mut came_from = vec![usize::MAX; self.blocked.len()];
+        let mut open = BinaryHeap::new();
+        cost[start] = 0;
+        open.push(Reverse((self.heuristic(start, goal), start)));
+
+        while let Some(Reverse((_, cell))) = open.pop() {
+            if cell == goal {
+                let mut path = vec![to.clone()];
+                let mut step = came_from[goal];
+                while step != usize::MAX && step != start {
+                    path.push(self.center(step));
+                    step = came_from[step];
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for (next, step_cost) in self.neighbours(cell) {
+                let next_cost = cost[cell] + step_cost;
+                if next_cost < cost[next] {
+                    cost[next] = next_cost;
+                    came_from[next] = cell;
+                    open.push(Reverse((next_cost + self.heuristic(next, goal), next)));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// A path a player is following, kept between ticks
+#[derive(Clone, Default, Debug, Component)]
+pub struct NavPath {
+    /// Where the path leads, as it was when the path was searched
+    pub target: Option<Position>,
+    /// Remaining waypoints, the next one last
+    pub waypoints: Vec<Position>,
+}
+
+/// Path queries for a single player, handed to behaviours in the [crate::behaviours::BehaviourContext]
+pub struct Navigator<'a> {
+    pub grid: &'a NavGrid,
+    pub path: &'a mut NavPath,
+    /// How far the target may move before the path is searched again
+    pub repath_distance: f32,
+}
+
+impl<'a> Navigator<'a> {
+    /// The point to head for next on the way from `from` to `to`.
+    /// The cached path is reused until `to` moves more than the repath distance.
+    pub fn next_waypoint(&mut self, from: &Position, to: &Position) -> Position {
+        if !self.grid.has_blocked() {
+            return to.clone();
+        }
+
+        let stale = self
+            .path
+            .target
+            .as_ref()
+            .map_or(true, |target| target.distance_to(to) > self.repath_distance);
+        if stale {
+            let mut waypoints = self.grid.find_path(from, to).unwrap_or_default();
+            waypoints.reverse();
+            self.path.waypoints = waypoints;
+            self.path.target = Some(to.clone());
+        }
+
+        // Waypoints count as reached within half a cell
+        let reach = self.grid.cell_size / 2.0;
+        while self.path.waypoints.len() > 1
+            && self
+                .path
+                .waypoints
+                .last()
+                .map_or(false, |waypoint| waypoint.distance_to(from) < reach)
+        {
+            self.path.waypoints.pop();
+        }
+
+        self.path
+            .waypoints
+            .last()
+            .cloned()
+            .unwrap_or_else(|| to.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::NavGrid;
+    use crate::entities_components::Position;
+
+    #[test]
+    fn test_path_around_wall() {
+        // A wall across the middle column, open only in the bottom row
+        let mut blocked = vec![false; 25];
+        for row in 0..4 {
+            blocked[row * 5 + 2] = true;
+        }
+        let grid = NavGrid::from_blocked(10.0, 5, 5, blocked);
+        assert!(grid.has_blocked());
+
+        let path = grid
+            .find_path(&Position::new(5.0, 5.0), &Position::new(45.0, 5.0))
+            .unwrap();
+
+        assert!(path.iter().any(|waypoint| waypoint.0[1] > 40.0));
+        assert_eq!(path.last(), Some(&Position::new(45.0, 5.0)));
+    }
+}
@@ -0,0 +1,281 @@
+use raylib::prelude::*;
+use shipyard::*;
+
+use crate::{
+    arena::{Shape, Terrain, TerrainLayer},
+    systems::endurance_velocity_scale,
+    Endurance, Flag, GameState, Match, Obstacle, PlayArea, Position, SimConfig, TagCount, TagState,
+    Tagged, Team, TeamScores, Velocity,
+};
+
+pub const FRAME: &str = "FRAME";
+
+/// Colours told apart in capture the flag, repeated if there are more teams
+const TEAM_COLORS: [Color; 4] = [Color::RED, Color::BLUE, Color::GREEN, Color::PURPLE];
+
+fn team_color(team: usize) -> Color {
+    TEAM_COLORS[team % TEAM_COLORS.len()]
+}
+
+fn terrain_color(terrain: Terrain) -> Color {
+    match terrain {
+        Terrain::Mud => Color::BROWN.fade(0.4),
+        Terrain::Ice => Color::SKYBLUE.fade(0.4),
+        Terrain::Rest => Color::LIME.fade(0.3),
+    }
+}
+
+/// Wrap raylib handler in a Shipyard ECS component.
+#[derive(Component)]
+pub struct RLHandle(pub raylib::RaylibHandle);
+
+/// Wrap raylib thread in a Shipyard ECS component.
+#[derive(Component)]
+pub struct RLThread(pub raylib::RaylibThread);
+
+impl Into<Vector2> for &Position {
+    fn into(self) -> Vector2 {
+        Vector2 {
+            x: self.0[0],
+            y: self.0[1],
+        }
+    }
+}
+
+pub fn window_should_close(world: &World) -> bool {
+    let ref rl = world.borrow::<UniqueViewMut<RLHandle>>().unwrap().0;
+    rl.window_should_close()
+}
+
+/// Systems drawing the `world`, run once per frame.
+/// Needs [RLHandle] and [RLThread] to be added to the `world`.
+pub fn register_frame_workload(world: &World) {
+    Workload::builder(FRAME)
+        // Render the players at the end of the frame tick
+        .with_system(render_players)
+        .add_to_world(&world)
+        .unwrap();
+}
+
+/// Render player positions into the raylib framebuffer.
+fn render_players(
+    uv_config: UniqueView<SimConfig>,
+    mut rlh: UniqueViewMut<RLHandle>,
+    rlt: NonSendSync<UniqueView<RLThread>>,
+    (positions, velocities, endurance, tagged): (
+        View<Position>,
+        View<Velocity>,
+        View<Endurance>,
+        View<Tagged>,
+    ),
+    (teams, flags, obstacles): (View<Team>, View<Flag>, View<Obstacle>),
+    (uv_terrain, uv_play_area): (UniqueView<TerrainLayer>, UniqueView<PlayArea>),
+    (uv_tag_count, uv_game_state, uv_match, uv_team_scores): (
+        UniqueView<TagCount>,
+        UniqueView<GameState>,
+        UniqueView<Match>,
+        UniqueView<TeamScores>,
+    ),
+) {
+    let mut d = rlh.0.begin_drawing(&rlt.0);
+
+    d.clear_background(Color::WHITE);
+
+    // Terrain is the background, everything else is drawn over it
+    for (cell, terrain) in uv_terrain.cells.iter().enumerate() {
+        if let Some(terrain) = terrain {
+            let size = uv_terrain.cell_size;
+            d.draw_rectangle_v(
+                Vector2::new(
+                    (cell % uv_terrain.cols) as f32 * size,
+                    (cell / uv_terrain.cols) as f32 * size,
+                ),
+                Vector2::new(size, size),
+                terrain_color(*terrain),
+            );
+        }
+    }
+
+    // Bases are highlighted over the terrain
+    for base in uv_config.safe_zones.iter() {
+        match base {
+            Shape::Circle { center, radius } => {
+                d.draw_circle(
+                    center[0] as i32,
+                    center[1] as i32,
+                    *radius,
+                    Color::GREEN.fade(0.2),
+                );
+                d.draw_circle_lines(
+                    center[0] as i32,
+                    center[1] as i32,
+                    *radius,
+                    Color::DARKGREEN,
+                );
+            }
+            Shape::Box { min, max } => {
+                let rectangle = Rectangle::new(min[0], min[1], max[0] - min[0], max[1] - min[1]);
+                d.draw_rectangle_rec(rectangle, Color::GREEN.fade(0.2));
+                d.draw_rectangle_lines_ex(rectangle, 1, Color::DARKGREEN);
+            }
+            Shape::Wall { .. } => {}
+        }
+    }
+
+    // Team territories are tinted, with the jail marked at their far end
+    for team in 0..uv_config.team_count() {
+        let zone = uv_config.home_zone(team);
+        d.draw_rectangle_v(
+            Vector2::new(zone.min[0], zone.min[1]),
+            Vector2::new(zone.max[0] - zone.min[0], zone.max[1] - zone.min[1]),
+            team_color(team).fade(0.08),
+        );
+        let jail = uv_config.jail(team);
+        d.draw_circle_lines(
+            jail.0[0] as i32,
+            jail.0[1] as i32,
+            uv_config.player_size * 3.0,
+            team_color(team),
+        );
+    }
+
+    for obstacle in obstacles.iter() {
+        match &obstacle.0 {
+            Shape::Circle { center, radius } => {
+                d.draw_circle(center[0] as i32, center[1] as i32, *radius, Color::DARKGRAY)
+            }
+            Shape::Box { min, max } => d.draw_rectangle_v(
+                Vector2::new(min[0], min[1]),
+                Vector2::new(max[0] - min[0], max[1] - min[1]),
+                Color::DARKGRAY,
+            ),
+            Shape::Wall { from, to } => d.draw_line_ex(
+                Vector2::new(from[0], from[1]),
+                Vector2::new(to[0], to[1]),
+                3.0,
+                Color::DARKGRAY,
+            ),
+        }
+    }
+
+    // The border of the shrinking play area
+    if uv_config.shrink.is_some() {
+        let area = &uv_play_area.0;
+        d.draw_rectangle_lines_ex(
+            Rectangle::new(
+                area.min[0],
+                area.min[1],
+                area.max[0] - area.min[0],
+                area.max[1] - area.min[1],
+            ),
+            3,
+            Color::RED,
+        );
+    }
+
+    // Each player is shown with position as dot, line as direction they're facing
+    for (pos, vel, endurance, tag) in (&positions, &velocities, &endurance, &tagged).iter() {
+        let tag = &tag.0;
+
+        let vel = endurance_velocity_scale(endurance, vel);
+
+        // The drawn direction vector is shown relative to the drawn player size.
+        let direction_vector = (vel.clone() * uv_config.player_size).0;
+
+        // Players that are "it" will have a different color.
+        let color = match tag {
+            TagState::NotIt => Color::BLACK,
+            TagState::It => Color::GOLD,
+            TagState::Frozen => Color::SKYBLUE,
+            TagState::Recovered => Color::LIME,
+            TagState::Eliminated => Color::LIGHTGRAY,
+            TagState::Jailed => Color::MAROON,
+        };
+        d.draw_circle(
+            pos.0[0] as i32,
+            pos.0[1] as i32,
+            uv_config.player_size,
+            color,
+        );
+        let ray_pos: Vector2 = pos.into();
+        let ray_end_pos: Vector2 = Vector2 {
+            x: ray_pos.x + (direction_vector[0]),
+            y: ray_pos.y + (direction_vector[1]),
+        };
+        d.draw_line_ex(
+            ray_pos,
+            ray_end_pos,
+            f32::max(uv_config.player_size * 0.5, 1.0),
+            Color::BLUE,
+        )
+    }
+
+    // Players wear the colour of their team as a ring
+    for (pos, team) in (&positions, &teams).iter() {
+        d.draw_circle_lines(
+            pos.0[0] as i32,
+            pos.0[1] as i32,
+            uv_config.player_size + 2.0,
+            team_color(team.0),
+        );
+    }
+
+    for (pos, flag) in (&positions, &flags).iter() {
+        let size = uv_config.player_size;
+        let pole_top = Vector2::new(pos.0[0] - size / 2.0, pos.0[1] - size * 2.0);
+        d.draw_rectangle_v(
+            pole_top,
+            Vector2::new(size * 1.5, size),
+            team_color(flag.team),
+        );
+        d.draw_line_ex(pole_top, pos.into(), 2.0, Color::DARKGRAY);
+    }
+
+    d.draw_text(
+        format!("Total taggings: {}", uv_tag_count.0).as_str(),
+        12,
+        12,
+        20,
+        Color::DARKPURPLE,
+    );
+
+    d.draw_text(
+        format!(
+            "Round {}/{}: {:?}",
+            uv_match.round, uv_config.rounds, *uv_game_state
+        )
+        .as_str(),
+        12,
+        36,
+        20,
+        Color::DARKPURPLE,
+    );
+
+    // Leading players of the match
+    for (rank, (player_id, wins)) in uv_match
+        .standings()
+        .into_iter()
+        .filter(|(_, wins)| *wins > 0)
+        .take(5)
+        .enumerate()
+    {
+        d.draw_text(
+            format!("#{} player {}: {} wins", rank + 1, player_id, wins).as_str(),
+            12,
+            60 + rank as i32 * 20,
+            16,
+            Color::DARKPURPLE,
+        );
+    }
+
+    // Flags captured by every team
+    for (team, captures) in uv_team_scores.0.iter().enumerate() {
+        d.draw_text(
+            format!("Team {}: {} flags", team + 1, captures).as_str(),
+            uv_config.width as i32 - 160,
+            12 + team as i32 * 24,
+            20,
+            team_color(team),
+        );
+    }
+}
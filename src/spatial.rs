@@ -0,0 +1,235 @@
+use spade::rtree::RTree;
+
+use crate::arena::{BoundaryPolicy, BoundaryRules};
+use crate::entities_components::{Geo2D, ObstacleData, Position, RTreeData, Velocity};
+
+/// The shape of the space distances are measured in.
+/// When players wrap the edges the arena is a torus, and the shortest way between two
+/// players may cross an edge.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Topology {
+    pub width: f32,
+    pub height: f32,
+    pub wraps: bool,
+}
+
+impl Topology {
+    /// The space as seen by a player following `policy`
+    pub fn for_policy(width: f32, height: f32, policy: BoundaryPolicy) -> Self {
+        Self {
+            width,
+            height,
+            wraps: policy == BoundaryPolicy::Wrap,
+        }
+    }
+
+    /// The space shared by all players. Players touch across an edge if anyone can wrap it.
+    pub fn for_rules(width: f32, height: f32, rules: &BoundaryRules) -> Self {
+        Self {
+            width,
+            height,
+            wraps: rules.not_it == BoundaryPolicy::Wrap || rules.it == BoundaryPolicy::Wrap,
+        }
+    }
+
+    /// Shortest vector leading from `from` to `to`
+    pub fn offset(&self, from: &Geo2D, to: &Geo2D) -> Geo2D {
+        let mut offset = [to[0] - from[0], to[1] - from[1]];
+        if self.wraps {
+            for (axis, size) in [self.width, self.height].iter().enumerate() {
+                if offset[axis] > size / 2.0 {
+                    offset[axis] -= size;
+                } else if offset[axis] < -size / 2.0 {
+                    offset[axis] += size;
+                }
+            }
+        }
+        offset
+    }
+
+    pub fn distance(&self, from: &Position, to: &Position) -> f32 {
+        let [dx, dy] = self.offset(&from.0, &to.0);
+        f32::sqrt(dx * dx + dy * dy)
+    }
+
+    /// Normalized direction along the shortest way from `from` to `to`
+    pub fn direction(&self, from: &Position, to: &Position) -> Velocity {
+        let [dx, dy] = self.offset(&from.0, &to.0);
+        Velocity::new(dx, dy).normalize()
+    }
+
+    /// Copies of `point` shifted by a whole arena, including `point` itself,
+    /// which lie within `radius` of the arena. Querying the R*-Tree at each copy
+    /// finds the players across the edges.
+    fn images(&self, point: &Geo2D, radius: f32) -> Vec<Geo2D> {
+        if !self.wraps {
+            return vec![*point];
+        }
+
+        let shifts = |size: f32| [0.0, -size, size];
+        let mut images = Vec::with_capacity(9);
+        for dx in shifts(self.width).iter() {
+            for dy in shifts(self.height).iter() {
+                let image = [point[0] + dx, point[1] + dy];
+                if self.distance_to_arena(&image) <= radius {
+                    images.push(image);
+                }
+            }
+        }
+        images
+    }
+
+    /// Distance from `point` to the closest point in the arena, `0.0` if inside
+    fn distance_to_arena(&self, point: &Geo2D) -> f32 {
+        let dx = f32::max(0.0, f32::max(-point[0], point[0] - self.width));
+        let dy = f32::max(0.0, f32::max(-point[1], point[1] - self.height));
+        f32::sqrt(dx * dx + dy * dy)
+    }
+}
+
+/// Neighbour queries over the [crate::PlayersPositionRTree] which respect a [Topology].
+/// The R*-Tree itself only knows plane distances, wrapping is handled by
+/// querying at every copy of the point across the edges.
+pub struct SpatialQuery<'a> {
+    pub rtree: &'a RTree<RTreeData>,
+    pub topology: Topology,
+}
+
+impl<'a> SpatialQuery<'a> {
+    pub fn new(rtree: &'a RTree<RTreeData>, topology: Topology) -> Self {
+        Self { rtree, topology }
+    }
+
+    /// The `k` players closest to `point`, closest first. Includes a player at `point` itself.
+    pub fn k_nearest(&self, point: &Position, k: usize) -> Vec<&'a RTreeData> {
+        let mut nearest = self.rtree.nearest_n_neighbors(&point.0, k);
+        if !self.topology.wraps {
+            return nearest;
+        }
+
+        // Only copies closer to the arena than the k-th neighbour can hold anyone closer
+        let radius = if nearest.len() < k {
+            f32::INFINITY
+        } else {
+            nearest
+                .last()
+                .map_or(f32::INFINITY, |n| point.distance_to(&n.position))
+        };
+        for image in self.topology.images(&point.0, radius).iter().skip(1) {
+            for candidate in self.rtree.nearest_n_neighbors(image, k) {
+                if !nearest.iter().any(|n| std::ptr::eq(*n, candidate)) {
+                    nearest.push(candidate);
+                }
+            }
+        }
+
+        self.sort_by_distance(point, &mut nearest);
+        nearest.truncate(k);
+        nearest
+    }
+
+    /// Every player within `radius` of `point`, closest first
+    pub fn within_radius(&self, point: &Position, radius: f32) -> Vec<&'a RTreeData> {
+        let radius2 = radius * radius;
+        let mut found: Vec<&'a RTreeData> = Vec::new();
+        for image in self.topology.images(&point.0, radius).iter() {
+            for candidate in self.rtree.lookup_in_circle(image, &radius2) {
+                if !found.iter().any(|n| std::ptr::eq(*n, candidate)) {
+                    found.push(candidate);
+                }
+            }
+        }
+
+        self.sort_by_distance(point, &mut found);
+        found
+    }
+
+    fn sort_by_distance(&self, point: &Position, players: &mut [&'a RTreeData]) {
+        players.sort_by(|a, b| {
+            let a = self.topology.distance(point, &a.position);
+            let b = self.topology.distance(point, &b.position);
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        });
+    }
+}
+
+/// Queries over the [crate::ObstaclesRTree].
+/// Obstacles do not wrap around the edges, so all queries are made on the plane.
+#[derive(Clone, Copy)]
+pub struct ObstacleQuery<'a> {
+    pub rtree: &'a RTree<ObstacleData>,
+}
+
+impl<'a> ObstacleQuery<'a> {
+    pub fn new(rtree: &'a RTree<ObstacleData>) -> Self {
+        Self { rtree }
+    }
+
+    /// Every obstacle within `radius` of `point`
+    pub fn within_radius(&self, point: &Position, radius: f32) -> Vec<&'a ObstacleData> {
+        self.rtree.lookup_in_circle(&point.0, &(radius * radius))
+    }
+
+    /// Whether nothing blocks the straight line from `from` to `to`
+    pub fn line_of_sight(&self, from: &Position, to: &Position) -> bool {
+        // Any obstacle crossing the line is within the circle around it
+        let center = Position::new((from.0[0] + to.0[0]) / 2.0, (from.0[1] + to.0[1]) / 2.0);
+        let radius = from.distance_to(to) / 2.0;
+        !self
+            .within_radius(&center, radius)
+            .iter()
+            .any(|obstacle| obstacle.shape.intersects_segment(&from.0, &to.0))
+    }
+
+    /// Push a player of `radius` at `pos` out of every obstacle it overlaps.
+    /// The velocity loses its part heading into the obstacle, keeping its speed,
+    /// such that the player slides along.
+    pub fn slide(&self, pos: &mut Geo2D, vel: &mut Velocity, radius: f32) {
+        let speed = vel.magnitude();
+        for obstacle in self.within_radius(&Position(*pos), radius) {
+            let (normal, depth) = match obstacle.shape.penetration(pos, radius) {
+                Some(penetration) => penetration,
+                None => continue,
+            };
+            pos[0] += normal[0] * depth;
+            pos[1] += normal[1] * depth;
+
+            let into = vel.0[0] * normal[0] + vel.0[1] * normal[1];
+            if into < 0.0 {
+                let along = vel.clone() - Velocity(normal) * into;
+                let along_speed = along.magnitude();
+                *vel = if along_speed > f32::EPSILON {
+                    along * (speed / along_speed)
+                } else {
+                    // Heading straight into the obstacle, turn back
+                    vel.clone().negate_velocity()
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Topology;
+    use crate::entities_components::Position;
+
+    #[test]
+    fn test_distance_across_edge() {
+        let torus = Topology {
+            width: 100.0,
+            height: 100.0,
+            wraps: true,
+        };
+        let plane = Topology {
+            wraps: false,
+            ..torus
+        };
+        let left = Position::new(1.0, 50.0);
+        let right = Position::new(99.0, 50.0);
+
+        assert!((torus.distance(&left, &right) - 2.0).abs() < 1e-4);
+        assert!((plane.distance(&left, &right) - 98.0).abs() < 1e-4);
+        assert!(torus.direction(&left, &right).0[0] < 0.0);
+    }
+}
@@ -1,75 +1,198 @@
-use std::borrow::BorrowMut;
+use std::time::Duration;
 
+use rand::seq::SliceRandom;
 use rand::Rng;
-use raylib::prelude::*;
 use shipyard::*;
 use spade::rtree::RTree;
 
 use crate::{
-    behaviours::{BehaviourAction, BehaviourContext},
+    arena::TerrainLayer,
+    behaviours::{
+        BehaviourContext, BehaviourRegistry, Blackboard, FlockWeights, Objectives, Steering,
+    },
     entities_components::{
-        PlayerBehaviour, PlayersPositionRTree, Position, RLHandle, RLThread, RTreeData,
-        RecentlyTagged, TagCount, TagState, Tagged, Time, Velocity,
+        BaseDwell, Flag, Obstacle, ObstacleData, ObstaclesRTree, PlayArea, PlayerBehaviour,
+        PlayerId, PlayerRng, PlayersPositionRTree, Position, RTreeData, RecentlyTagged, SimRng,
+        TagCount, TagState, Tagged, Team, TeamScores, Tick, Time, TimeAsIt, UnresolvedBehaviours,
+        Velocity,
     },
-    Endurance, HEIGHT, PLAYER_SIZE, WIDTH,
+    navigation::{NavGrid, NavPath, Navigator},
+    spatial::{ObstacleQuery, SpatialQuery},
+    Contact, Contacts, Endurance, EpidemicCurve, GameMode, Match, OutsidePenalty, SimConfig,
+    SirSample, TargetSelection,
 };
 
-pub fn window_should_close(world: &World) -> bool {
-    let ref rl = world.borrow::<UniqueViewMut<RLHandle>>().unwrap().0;
-    rl.window_should_close()
-}
-
-/// When the world has ben initalized with players, we can simply tag the first agent
+/// When the world has ben initalized with players, we can simply tag random agents
 /// as "it", such that the games can begin!
-pub fn tag_initial_random_player(mut tagged: ViewMut<Tagged>) {
-    let mut tagged = tagged
-        .borrow_mut()
-        .iter()
-        .next()
-        .expect("No entity with tagged component");
-    tagged.0 = TagState::It;
+pub fn tag_initial_random_players(
+    uv_config: UniqueView<SimConfig>,
+    mut uvm_rng: UniqueViewMut<SimRng>,
+    mut vm_tagged: ViewMut<Tagged>,
+) {
+    let players: Vec<EntityId> = (&vm_tagged).iter().with_id().map(|(id, _)| id).collect();
+    assert!(!players.is_empty(), "No entity with tagged component");
+
+    let it_ids: Vec<EntityId> = players
+        .choose_multiple(&mut uvm_rng.0, uv_config.it_count)
+        .cloned()
+        .collect();
+    for it_id in it_ids {
+        if let Ok(mut tagged) = (&mut vm_tagged).get(it_id) {
+            tagged.0 = TagState::It;
+        }
+    }
 }
 
 pub const TICK: &str = "TICK";
-pub const FRAME: &str = "FRAME";
 
 /// A collections of systems to run for each frame.
 pub fn register_workloads(world: &World) {
     Workload::builder(TICK)
         // Move players given their velocity every tick
+        .with_system(shrink_play_area)
         .with_system(update_player_position)
         .with_system(update_player_position_rtee)
         .with_system(commit_player_behaviour)
+        .with_system(integrate_steering)
+        .with_system(update_base_dwell)
         // Play tag
         .with_system(tag_collided_players)
+        .with_system(tag_intruders)
+        .with_system(unfreeze_touched_players)
+        .with_system(free_jailed_players)
+        .with_system(carry_flags)
+        .with_system(punish_players_outside)
+        .with_system(recover_infected_players)
+        .with_system(despawn_eliminated_players)
         // Clear recently tagged players
         .with_system(clear_old_recently_tagged)
         .with_system(regain_endurance)
+        .with_system(accumulate_time_as_it)
+        // Advance the simulated clock once the tick is done
+        .with_system(advance_simulation_clock)
+        .with_system(record_epidemic_curve)
         .add_to_world(&world)
         .unwrap();
 
-    Workload::builder(FRAME)
-        // Render the players at the end of the frame tick
-        .with_system(render_players)
-        .add_to_world(&world)
-        .unwrap();
+    #[cfg(feature = "render")]
+    crate::render::register_frame_workload(world);
+}
+
+/// Obstacles do not move, so they are indexed once when added to the `world`,
+/// instead of every tick. Run again after adding or removing obstacles.
+pub fn index_obstacles(
+    v_obstacle: View<Obstacle>,
+    mut uvm_obstacles: UniqueViewMut<ObstaclesRTree>,
+) {
+    uvm_obstacles.0 = RTree::bulk_load(
+        v_obstacle
+            .iter()
+            .with_id()
+            .map(|(entity_id, obstacle)| ObstacleData {
+                entity_id,
+                shape: obstacle.0.clone(),
+            })
+            .collect(),
+    );
+}
+
+/// Like the obstacles it is built from, the [NavGrid] is only built when the `world` is
+/// initialized. Run again after running [index_obstacles].
+pub fn build_nav_grid(
+    uv_config: UniqueView<SimConfig>,
+    uv_obstacles: UniqueView<ObstaclesRTree>,
+    mut uvm_nav_grid: UniqueViewMut<NavGrid>,
+) {
+    *uvm_nav_grid = NavGrid::new(
+        uv_config.width,
+        uv_config.height,
+        uv_config.nav_cell_size,
+        uv_config.player_size,
+        ObstacleQuery::new(&uv_obstacles.0),
+    );
+}
+
+/// The [TerrainLayer] is built once from [SimConfig::terrain], when the `world` is initialized.
+/// Cells line up with the map, if the arena was laid out after one.
+pub fn build_terrain_layer(
+    uv_config: UniqueView<SimConfig>,
+    mut uvm_terrain: UniqueViewMut<TerrainLayer>,
+) {
+    let cell_size = uv_config
+        .map
+        .as_ref()
+        .map_or(uv_config.nav_cell_size, |map| map.cell_size);
+    *uvm_terrain = TerrainLayer::new(
+        uv_config.width,
+        uv_config.height,
+        cell_size,
+        &uv_config.terrain,
+    );
+}
+
+/// Close the [PlayArea] in on the centre of the arena, as the round goes on
+fn shrink_play_area(
+    uv_config: UniqueView<SimConfig>,
+    uv_time: UniqueView<Time>,
+    uv_match: UniqueView<Match>,
+    mut uvm_play_area: UniqueViewMut<PlayArea>,
+) {
+    if uv_config.shrink.is_none() {
+        return;
+    }
+    let elapsed = Duration::from_millis((uv_time.0 - uv_match.state_changed_at) as u64);
+    uvm_play_area.0 = uv_config.play_area_at(elapsed);
 }
 
 /// Move players in accordance to their velocity
 fn update_player_position(
-    v_velocity: View<Velocity>,
+    uv_config: UniqueView<SimConfig>,
+    uv_obstacles: UniqueView<ObstaclesRTree>,
+    uv_terrain: UniqueView<TerrainLayer>,
+    v_tagged: View<Tagged>,
+    mut vm_velocity: ViewMut<Velocity>,
     mut vm_endurance: ViewMut<Endurance>,
     mut vm_position: ViewMut<Position>,
 ) {
-    for (pos, endurance, vel) in (&mut vm_position, &mut vm_endurance, &v_velocity).iter() {
+    for (pos, endurance, vel, tag) in (
+        &mut vm_position,
+        &mut vm_endurance,
+        &mut vm_velocity,
+        &v_tagged,
+    )
+        .iter()
+    {
+        // Frozen, eliminated and jailed players stay in place, and do not tire
+        if let TagState::Frozen | TagState::Eliminated | TagState::Jailed = tag.0 {
+            continue;
+        }
+
+        // Players wade slower through mud
+        let speed_factor = uv_terrain
+            .at(pos)
+            .map_or(1.0, |terrain| terrain.speed_factor());
         let geo_pos = &mut pos.0;
-        let geo_vel = endurance_velocity_scale(endurance, vel).0;
+        let geo_vel = (endurance_velocity_scale(endurance, vel) * speed_factor).0;
 
-        // A player will wrap around if they hit corners 2d-game style
-        geo_pos[0] = (geo_pos[0] + (geo_vel[0])).rem_euclid(WIDTH as f32);
-        geo_pos[1] = (geo_pos[1] + (geo_vel[1])).rem_euclid(HEIGHT as f32);
+        geo_pos[0] += geo_vel[0];
+        geo_pos[1] += geo_vel[1];
 
-        endurance.0 = u16::max(endurance.0 - 1, 1);
+        // Players running into obstacles slide along them
+        ObstacleQuery::new(&uv_obstacles.0).slide(geo_pos, vel, uv_config.player_size);
+
+        // Players hitting the edges wrap around 2d-game style, bounce or stop,
+        // depending on the rule for their tag state
+        uv_config.boundary_rules.for_state(&tag.0).apply(
+            geo_pos,
+            vel,
+            uv_config.width,
+            uv_config.height,
+        );
+
+        // Players catch their breath in bases
+        if uv_config.base_at(pos).is_none() {
+            endurance.0 = u16::max(endurance.0 - 1, 1);
+        }
     }
 }
 
@@ -79,6 +202,7 @@ fn update_player_position_rtee(
     v_velocity: View<Velocity>,
     v_tagged: View<Tagged>,
     v_recently_tagged: View<RecentlyTagged>,
+    v_team: View<Team>,
     mut uvm_player_pos_rtree: UniqueViewMut<PlayersPositionRTree>,
 ) {
     uvm_player_pos_rtree.0 = RTree::bulk_load(
@@ -91,6 +215,7 @@ fn update_player_position_rtee(
                 velocity: vel.clone(),
                 recently_tagged: recently_tagged.0.is_some(),
                 tagged: tag.clone(),
+                team: v_team.get(entity_id).ok().map(|team| team.0),
             })
             .collect(),
     );
@@ -98,124 +223,728 @@ fn update_player_position_rtee(
 
 /// Generate R*-Tree of all points - making nearest neighbour searches a breeze
 fn commit_player_behaviour(
-    v_position: View<Position>,
-    v_tagged: View<Tagged>,
-    v_player_behaviour: View<PlayerBehaviour>,
-    uv_player_rtree: UniqueView<PlayersPositionRTree>,
-    mut vm_velocity: ViewMut<Velocity>,
+    uv_config: UniqueView<SimConfig>,
+    (v_position, v_velocity, v_tagged): (View<Position>, View<Velocity>, View<Tagged>),
+    (v_player_behaviour, uv_registry, mut uvm_unresolved): (
+        View<PlayerBehaviour>,
+        UniqueView<BehaviourRegistry>,
+        UniqueViewMut<UnresolvedBehaviours>,
+    ),
+    (v_team, v_flag, v_endurance, v_flock_weights): (
+        View<Team>,
+        View<Flag>,
+        View<Endurance>,
+        View<FlockWeights>,
+    ),
+    (uv_player_rtree, uv_obstacles, uv_nav_grid, uv_terrain, uv_play_area): (
+        UniqueView<PlayersPositionRTree>,
+        UniqueView<ObstaclesRTree>,
+        UniqueView<NavGrid>,
+        UniqueView<TerrainLayer>,
+        UniqueView<PlayArea>,
+    ),
+    mut vm_player_rng: ViewMut<PlayerRng>,
+    (mut vm_nav_path, mut vm_steering, mut vm_blackboard): (
+        ViewMut<NavPath>,
+        ViewMut<Steering>,
+        ViewMut<Blackboard>,
+    ),
 ) {
-    for (pos, vel, tag, behaviour) in (
+    // Flags are few, and the same for every player
+    let flags: Vec<(Flag, Position)> = (&v_flag, &v_position)
+        .iter()
+        .map(|(flag, pos)| (flag.clone(), pos.clone()))
+        .collect();
+
+    for (entity_id, (pos, vel, endurance, tag, behaviour, rng, path, steering, blackboard)) in (
         &v_position,
-        &mut vm_velocity,
+        &v_velocity,
+        &v_endurance,
         &v_tagged,
         &v_player_behaviour,
+        &mut vm_player_rng,
+        &mut vm_nav_path,
+        &mut vm_steering,
+        &mut vm_blackboard,
     )
         .iter()
+        .with_id()
     {
         let tag = &tag.0;
+        let topology = uv_config.topology_for(tag);
 
         // 6 nearest neighbors including self
-        // But exclude first, as this will be the current point.
-        let nearest_5_neighbors = uv_player_rtree
-            .0
-            .nearest_neighbor_iterator(&pos.0)
-            .skip(1)
+        // But exclude the current player itself.
+        let players = SpatialQuery::new(&uv_player_rtree.0, topology);
+        let nearest_5_neighbors = players
+            .k_nearest(pos, 6)
+            .into_iter()
+            .filter(|n| n.entity_id != entity_id)
             .take(5)
             .collect();
 
+        let team = v_team.get(entity_id).ok().map(|team| team.0);
+        let objectives = match team {
+            Some(team) => Objectives {
+                own_flag: flags
+                    .iter()
+                    .find(|(flag, _)| flag.team == team)
+                    .map(|(_, pos)| pos.clone()),
+                enemy_flags: flags
+                    .iter()
+                    .filter(|(flag, _)| flag.team != team && flag.carrier.is_none())
+                    .map(|(_, pos)| pos.clone())
+                    .collect(),
+                home: Some(uv_config.flag_home(team)),
+                carrying_flag: flags
+                    .iter()
+                    .any(|(flag, _)| flag.carrier == Some(entity_id)),
+            },
+            None => Objectives::default(),
+        };
+
         // When evaluating the behaviour of the agent, some simple context is set up
-        let ctx = BehaviourContext {
+        let mut ctx = BehaviourContext {
+            entity_id,
             current_player: (pos, vel),
             distance_to_it: 0.0,
             nearest_5_neighbors,
+            players,
+            boundary: uv_config.boundary_rules.for_state(tag),
+            topology,
+            rng,
+            team,
+            objectives,
+            obstacles: ObstacleQuery::new(&uv_obstacles.0),
+            navigation: Navigator {
+                grid: &uv_nav_grid,
+                path,
+                repath_distance: uv_config.nav_repath_distance,
+            },
+            terrain: &uv_terrain,
+            endurance,
+            bases: &uv_config.safe_zones,
+            play_area: uv_play_area.0,
+            flock_weights: v_flock_weights
+                .get(entity_id)
+                .unwrap_or(&uv_config.flocking),
+            blackboard,
         };
 
-        // Behaviours dictate how the players act - mostly their orientation
-        match tag {
-            TagState::NotIt => behaviour.not_it_behaviour.revise_orientation(ctx),
-            TagState::It => behaviour.it_behaviour.revise_orientation(ctx),
+        // Behaviours dictate how the players want to act - mostly their orientation.
+        // Players whose behaviour is not registered keep their course, and are counted.
+        let name = match tag {
+            TagState::NotIt | TagState::Recovered => &behaviour.not_it_behaviour,
+            TagState::It => &behaviour.it_behaviour,
+            TagState::Frozen | TagState::Eliminated | TagState::Jailed => {
+                *steering = Steering::default();
+                continue;
+            }
+        };
+        *steering = match uv_registry.get(name) {
+            Some(action) => action.steer(&mut ctx),
+            None => {
+                uvm_unresolved.0 += 1;
+                Steering::default()
+            }
         };
     }
 }
 
-/// Players have their own behaviour which differs between when they're "it" and "not it"
-/// A player might wander aimlessly when "not it" but target nearest neighbours when "it"
-fn regain_endurance(v_tagged: View<Tagged>, mut vm_endurance: ViewMut<Endurance>) {
-    let mut rng = rand::thread_rng();
-
-    for (tag, endurance) in (&v_tagged, &mut vm_endurance).iter() {
-        match tag.0 {
-            TagState::NotIt => {
-                if rng.gen_bool(0.01) && endurance.0 < endurance.1 {
-                    endurance.0 += rng.gen_range(0..50);
+/// Turn the players and change their speed as their [Steering] asks, turning at most
+/// [SimConfig::max_turn_rate] and speeding up or slowing down at most [SimConfig::max_acceleration]
+/// every tick. On ice, players only manage part of the turn they ask for.
+fn integrate_steering(
+    uv_config: UniqueView<SimConfig>,
+    uv_terrain: UniqueView<TerrainLayer>,
+    v_position: View<Position>,
+    v_steering: View<Steering>,
+    mut vm_velocity: ViewMut<Velocity>,
+) {
+    use std::f32::consts::{PI, TAU};
+
+    for (pos, steering, vel) in (&v_position, &v_steering, &mut vm_velocity).iter() {
+        if steering.is_idle() {
+            continue;
+        }
+
+        let speed = vel.magnitude();
+        let heading = match &steering.direction {
+            // Standing still, the player can face any way
+            Some(direction) if speed == 0.0 => direction.clone(),
+            _ => vel.clone(),
+        };
+        let wanted_turn = steering
+            .direction
+            .as_ref()
+            .map_or(0.0, |direction| heading.angle_between(direction))
+            + steering.turn;
+        // The shortest way around
+        let wanted_turn = (wanted_turn + PI).rem_euclid(TAU) - PI;
+        let grip = uv_terrain.at(pos).map_or(1.0, |terrain| terrain.grip());
+        let turn = (wanted_turn * grip).clamp(-uv_config.max_turn_rate, uv_config.max_turn_rate);
+
+        let acceleration = uv_config.max_acceleration;
+        let wanted_speed = steering.speed.unwrap_or(speed);
+        let new_speed = speed + (wanted_speed - speed).clamp(-acceleration, acceleration);
+
+        let heading = heading.rotate_angle(turn);
+        if heading.magnitude() > 0.0 {
+            *vel = heading.normalize() * new_speed;
+        }
+    }
+}
+
+/// Players outside of the [PlayArea] lose endurance, or are tagged,
+/// depending on the [crate::OutsidePenalty] of the [SimConfig::shrink] schedule
+fn punish_players_outside(
+    uv_config: UniqueView<SimConfig>,
+    uv_play_area: UniqueView<PlayArea>,
+    v_position: View<Position>,
+    mut uvm_tag_count: UniqueViewMut<TagCount>,
+    mut vm_endurance: ViewMut<Endurance>,
+    mut vm_tagged: ViewMut<Tagged>,
+) {
+    let penalty = match &uv_config.shrink {
+        Some(schedule) => schedule.penalty,
+        None => return,
+    };
+
+    for (pos, endurance, tag) in (&v_position, &mut vm_endurance, &mut vm_tagged).iter() {
+        if uv_play_area.0.contains(pos) {
+            continue;
+        }
+        match penalty {
+            OutsidePenalty::Endurance(amount) => {
+                endurance.0 = u16::max(endurance.0.saturating_sub(amount), 1);
+            }
+            OutsidePenalty::Tag => {
+                let untagged = tag.0 == TagState::NotIt || tag.0 == TagState::Recovered;
+                if let Some(target_state) = uv_config.game_mode.tagged_state().filter(|_| untagged)
+                {
+                    tag.0 = target_state;
+                    uvm_tag_count.0 += 1;
                 }
             }
-            TagState::It => {
-                if rng.gen_bool(0.05) && endurance.0 < endurance.1 {
-                    endurance.0 += rng.gen_range(0..50);
+        }
+    }
+}
+
+/// Players in a base cannot be tagged, until they have stayed longer than
+/// [SimConfig::max_base_dwell]. They are then turned out of the base the shortest way,
+/// and are turned out of every base they come back to until they have kept away for
+/// as long again. All the while they can be tagged.
+fn update_base_dwell(
+    uv_config: UniqueView<SimConfig>,
+    uv_time: UniqueView<Time>,
+    v_position: View<Position>,
+    v_tagged: View<Tagged>,
+    mut vm_velocity: ViewMut<Velocity>,
+    mut vm_base_dwell: ViewMut<BaseDwell>,
+) {
+    if uv_config.safe_zones.is_empty() {
+        return;
+    }
+
+    for (pos, tag, vel, dwell) in
+        (&v_position, &v_tagged, &mut vm_velocity, &mut vm_base_dwell).iter()
+    {
+        let base = match uv_config.base_at(pos) {
+            Some(base) if tag.0 != TagState::It => base,
+            _ => {
+                dwell.entered = None;
+                let ended = match (dwell.evicted, uv_config.max_base_dwell) {
+                    (Some(evicted), Some(max_dwell)) => {
+                        uv_time.0 - evicted >= max_dwell.as_millis()
+                    }
+                    _ => true,
+                };
+                if ended {
+                    dwell.evicted = None;
                 }
+                continue;
             }
+        };
+
+        let entered = *dwell.entered.get_or_insert(uv_time.0);
+        if let Some(max_dwell) = uv_config.max_base_dwell {
+            if dwell.evicted.is_some() || uv_time.0 - entered >= max_dwell.as_millis() {
+                dwell.evicted = Some(uv_time.0);
+            }
+        }
+        if dwell.evicted.is_some() {
+            if let Some((normal, _)) = base.penetration(&pos.0, 0.0) {
+                *vel = Velocity::new(normal[0], normal[1]) * vel.magnitude();
+            }
+        }
+    }
+}
+
+/// Players have their own behaviour which differs between when they're "it" and "not it"
+/// A player might wander aimlessly when "not it" but target nearest neighbours when "it"
+fn regain_endurance(
+    uv_config: UniqueView<SimConfig>,
+    uv_terrain: UniqueView<TerrainLayer>,
+    v_position: View<Position>,
+    v_tagged: View<Tagged>,
+    mut vm_endurance: ViewMut<Endurance>,
+    mut vm_player_rng: ViewMut<PlayerRng>,
+) {
+    for (pos, tag, endurance, rng) in (
+        &v_position,
+        &v_tagged,
+        &mut vm_endurance,
+        &mut vm_player_rng,
+    )
+        .iter()
+    {
+        let rng = &mut rng.0;
+
+        let regen_chance = match tag.0 {
+            TagState::NotIt
+            | TagState::Frozen
+            | TagState::Recovered
+            | TagState::Eliminated
+            | TagState::Jailed => uv_config.not_it_regen_chance,
+            TagState::It => uv_config.it_regen_chance,
+        };
+        // Rest zones make regaining endurance more likely
+        let regen_chance = uv_terrain.at(pos).map_or(regen_chance, |terrain| {
+            f64::min(regen_chance * terrain.regen_factor(), 1.0)
+        });
+
+        if rng.gen_bool(regen_chance) && endurance.0 < endurance.1 {
+            endurance.0 += rng.gen_range(uv_config.regen_amount.clone());
         }
     }
 }
 
 /// Tag players that collide with "it" players.
-/// Using the [PlayersPositionRTree] it is possible to do this with good performance.
+/// Every "it" player looks for all players within reach in the [PlayersPositionRTree],
+/// and picks whom to tag according to [SimConfig::target_selection].
+/// All contacts are stored in [Contacts] for other systems to use.
 fn tag_collided_players(
-    v_position: View<Position>,
+    uv_config: UniqueView<SimConfig>,
     uv_time: UniqueView<Time>,
     uv_player_rtree: UniqueView<PlayersPositionRTree>,
-    mut uvm_tag_count: UniqueViewMut<TagCount>,
-    mut vm_recently_tagged: ViewMut<RecentlyTagged>,
-    mut vm_tagged: ViewMut<Tagged>,
+    (v_endurance, v_base_dwell): (View<Endurance>, View<BaseDwell>),
+    (mut uvm_rng, mut uvm_contacts, mut uvm_tag_count): (
+        UniqueViewMut<SimRng>,
+        UniqueViewMut<Contacts>,
+        UniqueViewMut<TagCount>,
+    ),
+    (mut vm_recently_tagged, mut vm_tagged): (ViewMut<RecentlyTagged>, ViewMut<Tagged>),
 ) {
-    // The currently, and only, tagged player
-    let tagged_it = (&v_position, &vm_tagged)
+    // What becomes of the tagged player, and of the "it" player who tagged them
+    let target_state = match uv_config.game_mode.tagged_state() {
+        Some(target_state) => target_state,
+        // Played in teams, see [tag_intruders]
+        None => return,
+    };
+    let it_state = match uv_config.game_mode {
+        GameMode::Classic => Some(TagState::NotIt),
+        _ => None,
+    };
+
+    let topology = uv_config.topology();
+    let query = SpatialQuery::new(&uv_player_rtree.0, topology);
+    let tag_distance = uv_config.player_size * 2.0;
+    let in_base = |entity_id: EntityId| {
+        v_base_dwell
+            .get(entity_id)
+            .map_or(false, |dwell| dwell.is_safe())
+    };
+
+    // Every player within reach of an "it" player
+    let contacts: Vec<Contact> = uv_player_rtree
+        .0
         .iter()
-        .with_id()
-        .find(|(_, (_, tag))| tag.0 == TagState::It)
-        .clone();
-
-    let mut have_tagged_new = false;
-    if let Some((it_id, (it_pos, _))) = tagged_it {
-        let closest = uv_player_rtree.0.nearest_n_neighbors(&it_pos.0, 2);
-        // skip the first, as that would be the current "it" player
-        if let Some(RTreeData { entity_id, .. }) = closest.get(1) {
-            if let Ok((position, mut tagged, mut recently_tagged)) =
-                (&v_position, &mut vm_tagged, &mut vm_recently_tagged).get(entity_id.clone())
-            {
-                if tagged.0 != TagState::It
-                    && recently_tagged.0.is_none()
-                    && it_pos.distance_to(position) <= PLAYER_SIZE * 2.0
-                {
-                    have_tagged_new = true;
-                    tagged.0 = TagState::It;
+        .filter(|it| it.tagged.0 == TagState::It)
+        .flat_map(|it| {
+            query
+                .within_radius(&it.position, tag_distance)
+                .into_iter()
+                .filter(move |other| other.entity_id != it.entity_id)
+                .map(move |other| Contact {
+                    it: it.entity_id,
+                    other: other.entity_id,
+                    distance: topology.distance(&it.position, &other.position),
+                    taggable: other.tagged.0 == TagState::NotIt
+                        && !other.recently_tagged
+                        && !in_base(other.entity_id),
+                })
+        })
+        .collect();
 
-                    recently_tagged.0 = Some(uv_time.0);
-                    uvm_tag_count.0 += 1;
+    // Rank the possible taggings, lowest first
+    let mut ranked: Vec<([f32; 2], &Contact)> = contacts
+        .iter()
+        .filter(|contact| contact.taggable)
+        .map(|contact| {
+            let rank = match uv_config.target_selection {
+                TargetSelection::Nearest => [contact.distance, 0.0],
+                TargetSelection::Random => [uvm_rng.0.gen::<f32>(), 0.0],
+                TargetSelection::LowestEndurance => {
+                    let endurance = v_endurance.get(contact.other).map_or(0, |e| e.0);
+                    [endurance as f32, contact.distance]
                 }
-            }
+            };
+            (rank, contact)
+        })
+        .collect();
+    ranked.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+    // When "it" players compete for the same target, the best ranked tagging wins.
+    // Every player is tagged at most once per tick, and "it" players who hand
+    // over being "it" can only do so once.
+    let mut involved: Vec<EntityId> = Vec::new();
+    for (_, contact) in ranked {
+        if involved.contains(&contact.other)
+            || (it_state.is_some() && involved.contains(&contact.it))
+        {
+            continue;
         }
+        involved.push(contact.it);
+        involved.push(contact.other);
 
-        // If the tagging has been replaced, update the now previously tagged player.
-        if have_tagged_new {
+        // Players changing state are marked as recently tagged, so they cannot be tagged right back.
+        let changes = std::iter::once((contact.other, target_state.clone()))
+            .chain(it_state.clone().map(|state| (contact.it, state)));
+        for (entity_id, new_state) in changes {
             if let Ok((mut recently_tagged, mut tagged)) =
-                (&mut vm_recently_tagged, &mut vm_tagged).get(it_id)
+                (&mut vm_recently_tagged, &mut vm_tagged).get(entity_id)
             {
-                tagged.0 = TagState::NotIt;
-                // mark previously-tagged player as recently tagged
+                tagged.0 = new_state;
                 recently_tagged.0 = Some(uv_time.0);
+            }
+        }
 
-                // Increment total tag count
-            };
+        // Increment total tag count
+        uvm_tag_count.0 += 1;
+    }
+
+    uvm_contacts.0 = contacts;
+}
+
+/// In freeze tag, frozen players are freed when a "not it" player touches them.
+fn unfreeze_touched_players(
+    uv_config: UniqueView<SimConfig>,
+    uv_time: UniqueView<Time>,
+    uv_player_rtree: UniqueView<PlayersPositionRTree>,
+    mut vm_recently_tagged: ViewMut<RecentlyTagged>,
+    mut vm_tagged: ViewMut<Tagged>,
+) {
+    if uv_config.game_mode != GameMode::FreezeTag {
+        return;
+    }
+
+    let query = SpatialQuery::new(&uv_player_rtree.0, uv_config.topology());
+    let touch_distance = uv_config.player_size * 2.0;
+
+    let freed: Vec<EntityId> = uv_player_rtree
+        .0
+        .iter()
+        .filter(|frozen| frozen.tagged.0 == TagState::Frozen)
+        .filter(|frozen| {
+            query
+                .within_radius(&frozen.position, touch_distance)
+                .iter()
+                .any(|other| other.tagged.0 == TagState::NotIt)
+        })
+        .map(|frozen| frozen.entity_id)
+        .collect();
+
+    for entity_id in freed {
+        if let Ok((mut recently_tagged, mut tagged)) =
+            (&mut vm_recently_tagged, &mut vm_tagged).get(entity_id)
+        {
+            tagged.0 = TagState::NotIt;
+            // A freed player gets a head start before it can be frozen again
+            recently_tagged.0 = Some(uv_time.0);
+        }
+    }
+}
+
+/// In capture the flag, players in their own territory tag the players of other teams
+/// who have entered it. Tagged players are sent to the jail of the territory.
+/// All contacts are stored in [Contacts], with the defending player as `it`.
+fn tag_intruders(
+    uv_config: UniqueView<SimConfig>,
+    uv_time: UniqueView<Time>,
+    uv_player_rtree: UniqueView<PlayersPositionRTree>,
+    (mut uvm_contacts, mut uvm_tag_count): (UniqueViewMut<Contacts>, UniqueViewMut<TagCount>),
+    (mut vm_position, mut vm_recently_tagged, mut vm_tagged): (
+        ViewMut<Position>,
+        ViewMut<RecentlyTagged>,
+        ViewMut<Tagged>,
+    ),
+) {
+    if uv_config.team_count() == 0 {
+        return;
+    }
+
+    let config = &*uv_config;
+    let topology = config.topology();
+    let query = SpatialQuery::new(&uv_player_rtree.0, topology);
+    let tag_distance = config.player_size * 2.0;
+
+    // Every player of another team within reach of a player at home
+    let contacts: Vec<Contact> = uv_player_rtree
+        .0
+        .iter()
+        .filter(|defender| defender.tagged.0 != TagState::Jailed)
+        .filter_map(|defender| defender.team.map(|team| (defender, team)))
+        .filter(|(defender, team)| config.territory_of(&defender.position) == Some(*team))
+        .flat_map(|(defender, team)| {
+            query
+                .within_radius(&defender.position, tag_distance)
+                .into_iter()
+                .filter(move |other| other.team.map_or(false, |other_team| other_team != team))
+                .map(move |other| Contact {
+                    it: defender.entity_id,
+                    other: other.entity_id,
+                    distance: topology.distance(&defender.position, &other.position),
+                    taggable: other.tagged.0 != TagState::Jailed
+                        && !other.recently_tagged
+                        && config.territory_of(&other.position) == Some(team),
+                })
+        })
+        .collect();
+
+    let mut taggable: Vec<&Contact> = contacts.iter().filter(|c| c.taggable).collect();
+    taggable.sort_by(|a, b| {
+        a.distance
+            .partial_cmp(&b.distance)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    // Any number of defenders may tag at once, but every intruder is jailed only once
+    let mut jailed: Vec<EntityId> = Vec::new();
+    for contact in taggable {
+        if jailed.contains(&contact.other) {
+            continue;
+        }
+        jailed.push(contact.other);
+
+        if let Ok((mut pos, mut recently_tagged, mut tagged)) =
+            (&mut vm_position, &mut vm_recently_tagged, &mut vm_tagged).get(contact.other)
+        {
+            if let Some(team) = config.territory_of(&pos) {
+                *pos = config.jail(team);
+            }
+            tagged.0 = TagState::Jailed;
+            recently_tagged.0 = Some(uv_time.0);
+        }
+
+        uvm_tag_count.0 += 1;
+    }
+
+    uvm_contacts.0 = contacts;
+}
+
+/// In capture the flag, jailed players are freed when a teammate touches them.
+/// Freed players go after the enemy flags.
+fn free_jailed_players(
+    uv_config: UniqueView<SimConfig>,
+    uv_time: UniqueView<Time>,
+    uv_player_rtree: UniqueView<PlayersPositionRTree>,
+    mut vm_recently_tagged: ViewMut<RecentlyTagged>,
+    mut vm_tagged: ViewMut<Tagged>,
+) {
+    if uv_config.team_count() == 0 {
+        return;
+    }
+
+    let query = SpatialQuery::new(&uv_player_rtree.0, uv_config.topology());
+    let touch_distance = uv_config.player_size * 2.0;
+
+    let freed: Vec<EntityId> = uv_player_rtree
+        .0
+        .iter()
+        .filter(|jailed| jailed.tagged.0 == TagState::Jailed)
+        .filter(|jailed| {
+            query
+                .within_radius(&jailed.position, touch_distance)
+                .iter()
+                .any(|other| other.team == jailed.team && other.tagged.0 != TagState::Jailed)
+        })
+        .map(|jailed| jailed.entity_id)
+        .collect();
+
+    for entity_id in freed {
+        if let Ok((mut recently_tagged, mut tagged)) =
+            (&mut vm_recently_tagged, &mut vm_tagged).get(entity_id)
+        {
+            tagged.0 = TagState::NotIt;
+            // A freed player gets a head start to leave enemy territory
+            recently_tagged.0 = Some(uv_time.0);
         }
     }
 }
 
-// 5 seconds after tagging, players will be removed from the
+/// In capture the flag, flags are picked up by players of other teams touching them,
+/// and follow their carrier. A flag brought into the territory of the carrier's team
+/// scores a capture, and a flag whose carrier got jailed returns home.
+fn carry_flags(
+    uv_config: UniqueView<SimConfig>,
+    uv_player_rtree: UniqueView<PlayersPositionRTree>,
+    v_tagged: View<Tagged>,
+    v_team: View<Team>,
+    mut uvm_team_scores: UniqueViewMut<TeamScores>,
+    mut vm_flag: ViewMut<Flag>,
+    mut vm_position: ViewMut<Position>,
+) {
+    if uv_config.team_count() == 0 {
+        return;
+    }
+
+    let query = SpatialQuery::new(&uv_player_rtree.0, uv_config.topology());
+    let reach = uv_config.player_size * 2.0;
+
+    // A player carries at most one flag
+    let mut carriers: Vec<EntityId> = (&vm_flag).iter().filter_map(|flag| flag.carrier).collect();
+
+    for (flag_id, flag) in (&mut vm_flag).iter().with_id() {
+        let carrier = match flag.carrier {
+            Some(carrier) => carrier,
+            None => {
+                // Picked up by the closest free player of another team
+                let flag_pos = match (&vm_position).get(flag_id) {
+                    Ok(pos) => pos.clone(),
+                    Err(_) => continue,
+                };
+                flag.carrier = query
+                    .within_radius(&flag_pos, reach)
+                    .iter()
+                    .filter(|p| p.team.map_or(false, |team| team != flag.team))
+                    .filter(|p| p.tagged.0 != TagState::Jailed)
+                    .map(|p| p.entity_id)
+                    .find(|entity_id| !carriers.contains(entity_id));
+                carriers.extend(flag.carrier);
+                continue;
+            }
+        };
+
+        let free = v_tagged
+            .get(carrier)
+            .map_or(false, |tag| tag.0 != TagState::Jailed);
+        let carrier_pos = (&vm_position).get(carrier).ok().cloned();
+        let carrier_team = v_team.get(carrier).ok().map(|team| team.0);
+
+        let new_pos = match carrier_pos {
+            Some(carrier_pos) if free => {
+                if carrier_team.is_some() && uv_config.territory_of(&carrier_pos) == carrier_team {
+                    if let Some(score) =
+                        carrier_team.and_then(|team| uvm_team_scores.0.get_mut(team))
+                    {
+                        *score += 1;
+                    }
+                    flag.carrier = None;
+                    uv_config.flag_home(flag.team)
+                } else {
+                    carrier_pos
+                }
+            }
+            // Dropped flags return home
+            _ => {
+                flag.carrier = None;
+                uv_config.flag_home(flag.team)
+            }
+        };
+
+        if let Ok(mut pos) = (&mut vm_position).get(flag_id) {
+            *pos = new_pos;
+        }
+    }
+}
+
+/// In elimination, tagged players are removed from the `world` if the mode asks for it.
+/// Otherwise they stay in the arena for the rest of the round, without moving.
+fn despawn_eliminated_players(mut all_storages: AllStoragesViewMut) {
+    let despawn = matches!(
+        all_storages
+            .borrow::<UniqueView<SimConfig>>()
+            .unwrap()
+            .game_mode,
+        GameMode::Elimination {
+            despawn_tagged: true
+        }
+    );
+    if !despawn {
+        return;
+    }
+
+    let eliminated: Vec<EntityId> = all_storages
+        .borrow::<View<Tagged>>()
+        .unwrap()
+        .iter()
+        .with_id()
+        .filter(|(_, tag)| tag.0 == TagState::Eliminated)
+        .map(|(entity_id, _)| entity_id)
+        .collect();
+    for entity_id in eliminated {
+        all_storages.delete_entity(entity_id);
+    }
+}
+
+/// In infection tag, infected players recover once they have been "it" for the recovery time.
+/// As a player can only be infected once, the time as "it" is the time since infection.
+fn recover_infected_players(
+    uv_config: UniqueView<SimConfig>,
+    v_time_as_it: View<TimeAsIt>,
+    mut vm_tagged: ViewMut<Tagged>,
+) {
+    let recovery = match uv_config.game_mode {
+        GameMode::Infection {
+            recovery: Some(recovery),
+        } => recovery,
+        _ => return,
+    };
+
+    let tick_micros = uv_config.tick_duration.as_micros();
+    for (tag, time_as_it) in (&mut vm_tagged, &v_time_as_it).iter() {
+        if tag.0 == TagState::It && time_as_it.0 as u128 * tick_micros >= recovery.as_micros() {
+            tag.0 = TagState::Recovered;
+        }
+    }
+}
+
+/// Sample the susceptible, infected and recovered players of infection tag.
+/// Players out of play in any other way can neither catch nor spread "it",
+/// and are counted as recovered such that every player is in one of the groups.
+fn record_epidemic_curve(
+    uv_config: UniqueView<SimConfig>,
+    uv_tick: UniqueView<Tick>,
+    uv_time: UniqueView<Time>,
+    v_tagged: View<Tagged>,
+    mut uvm_epidemic_curve: UniqueViewMut<EpidemicCurve>,
+) {
+    if let GameMode::Infection { .. } = uv_config.game_mode {
+        let mut sample = SirSample {
+            tick: uv_tick.0,
+            time: uv_time.0,
+            susceptible: 0,
+            infected: 0,
+            recovered: 0,
+        };
+        for tag in v_tagged.iter() {
+            match tag.0 {
+                TagState::NotIt => sample.susceptible += 1,
+                TagState::It => sample.infected += 1,
+                TagState::Recovered
+                | TagState::Frozen
+                | TagState::Eliminated
+                | TagState::Jailed => sample.recovered += 1,
+            }
+        }
+        uvm_epidemic_curve.0.push(sample);
+    }
+}
+
+// After the configured cooldown, players will be removed from the
 // recently-tagged players map
-const CLEAR_AFTER_SECONDS: u128 = 5 * 1000; // milliseconds
 fn clear_old_recently_tagged(
+    uv_config: UniqueView<SimConfig>,
     uv_time: UniqueView<Time>,
     mut vm_recently_tagged: ViewMut<RecentlyTagged>,
     // mut recently_tagged: UniqueViewMut<RecentlyTagged>,
@@ -223,66 +952,45 @@ fn clear_old_recently_tagged(
     // Only keep recent players if their timestamp is within the given constant
     (&mut vm_recently_tagged).iter().for_each(|t| {
         if let Some(timestamp) = t.0 {
-            if timestamp + CLEAR_AFTER_SECONDS <= uv_time.0 {
+            if timestamp + uv_config.tag_cooldown.as_millis() <= uv_time.0 {
                 t.0.take();
             }
         }
     });
 }
 
-/// Render player positions into the raylib framebuffer.
-fn render_players(
-    mut rlh: UniqueViewMut<RLHandle>,
-    rlt: NonSendSync<UniqueView<RLThread>>,
-    positions: View<Position>,
-    velocities: View<Velocity>,
-    endurance: View<Endurance>,
-    tagged: View<Tagged>,
-    uv_tag_count: UniqueView<TagCount>,
+/// Keep track of how long every player has been "it", in the round with [TimeAsIt]
+/// and over the whole match in [Match::ticks_as_it]
+fn accumulate_time_as_it(
+    v_tagged: View<Tagged>,
+    v_player_id: View<PlayerId>,
+    mut uvm_match: UniqueViewMut<Match>,
+    mut vm_time_as_it: ViewMut<TimeAsIt>,
 ) {
-    let mut d = rlh.0.begin_drawing(&rlt.0);
-
-    d.clear_background(Color::WHITE);
-
-    // Each player is shown with position as dot, line as direction they're facing
-    for (pos, vel, endurance, tag) in (&positions, &velocities, &endurance, &tagged).iter() {
-        let tag = &tag.0;
-
-        let vel = endurance_velocity_scale(endurance, vel);
-
-        // The drawn direction vector is shown relative to the drawn player size.
-        let direction_vector = (vel.clone() * PLAYER_SIZE).0;
-
-        // Players that are "it" will have a different color.
-        let color = match tag {
-            TagState::NotIt => Color::BLACK,
-            TagState::It => Color::GOLD,
-        };
-        d.draw_circle(pos.0[0] as i32, pos.0[1] as i32, PLAYER_SIZE, color);
-        let ray_pos: Vector2 = pos.into();
-        let ray_end_pos: Vector2 = Vector2 {
-            x: ray_pos.x + (direction_vector[0]),
-            y: ray_pos.y + (direction_vector[1]),
-        };
-        d.draw_line_ex(
-            ray_pos,
-            ray_end_pos,
-            f32::max(PLAYER_SIZE * 0.5, 1.0),
-            Color::BLUE,
-        )
+    for (tag, player_id, time_as_it) in (&v_tagged, &v_player_id, &mut vm_time_as_it).iter() {
+        if tag.0 == TagState::It {
+            time_as_it.0 += 1;
+            if uvm_match.ticks_as_it.len() <= player_id.0 {
+                uvm_match.ticks_as_it.resize(player_id.0 + 1, 0);
+            }
+            uvm_match.ticks_as_it[player_id.0] += 1;
+        }
     }
+}
 
-    d.draw_text(
-        format!("Total taggings: {}", uv_tag_count.0).as_str(),
-        12,
-        12,
-        20,
-        Color::DARKPURPLE,
-    );
+/// Step the simulated clock by one tick.
+/// [Time] is derived from the tick count, so it does not drift no matter how fast ticks are run.
+pub(crate) fn advance_simulation_clock(
+    uv_config: UniqueView<SimConfig>,
+    mut uvm_tick: UniqueViewMut<Tick>,
+    mut uvm_time: UniqueViewMut<Time>,
+) {
+    uvm_tick.0 += 1;
+    uvm_time.0 = uvm_tick.0 as u128 * uv_config.tick_duration.as_micros() / 1000;
 }
 
 /// Helper function to calculated endurace-scaled velocity
-fn endurance_velocity_scale(endurance: &Endurance, vel: &Velocity) -> Velocity {
+pub(crate) fn endurance_velocity_scale(endurance: &Endurance, vel: &Velocity) -> Velocity {
     let Endurance(current_endurance, max_endurance) = *endurance;
     let endurance_factor = 1.0 - current_endurance as f32 / max_endurance as f32;
     vel.clone() - (vel.clone() / 2. * endurance_factor)